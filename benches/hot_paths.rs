@@ -0,0 +1,126 @@
+//! Benchmarks of the hot paths of [`LWWMap`] and [`LWWRegister`], run with `cargo bench`.
+//!
+//! This is a plain timing loop rather than a benchmark framework, so that the crate keeps no
+//! dependencies. Every benchmark runs its routine for about [`TARGET`] and reports the mean time
+//! of an iteration, with the fixture of every iteration built outside of the timed section.
+//! An argument filters the benchmarks by name, e.g `cargo bench -- merge`
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use replixel::crdt::lww::map::{Entry, LWWMap, MapState};
+use replixel::crdt::lww::register::{LWWRegister, LWWState};
+use replixel::crdt::replica::ReplicaId;
+use replixel::crdt::CRDT;
+
+/// The number of keys of the maps
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+
+/// The number of bytes of the values of the registers
+const VALUE_SIZES: [usize; 3] = [16, 1_024, 65_536];
+
+/// How long every benchmark runs for
+const TARGET: Duration = Duration::from_millis(500);
+
+const LOCAL: ReplicaId = ReplicaId::new(1);
+
+const REMOTE: ReplicaId = ReplicaId::new(2);
+
+/// Run `routine` over fixtures built by `setup` for about [`TARGET`] and print the mean time of
+/// an iteration, unless `name` does not contain `filter`
+fn bench<T, R>(
+    filter: Option<&str>,
+    name: &str,
+    mut setup: impl FnMut() -> T,
+    mut routine: impl FnMut(T) -> R,
+) {
+    if filter.is_some_and(|filter| !name.contains(filter)) {
+        return;
+    }
+
+    let (mut iterations, mut elapsed) = (0u32, Duration::ZERO);
+    while elapsed < TARGET {
+        let fixture = setup();
+        let start = Instant::now();
+        black_box(routine(black_box(fixture)));
+        elapsed += start.elapsed();
+        iterations += 1;
+    }
+
+    println!(
+        "{name:<32} {:>12.3?}/iter ({iterations} iterations)",
+        elapsed / iterations
+    );
+}
+
+/// A state of `size` live keys, all written once by `replica`
+fn converged(size: usize, replica: ReplicaId) -> MapState<u64, u64> {
+    MapState::from_versions((0..size as u64).map(|k| (k, Entry::Occupied(k), 1, replica)))
+}
+
+/// A state that diverged from [`converged`] on every key: a third of the keys have been written
+/// again, a third have been removed, and the others have been written concurrently
+fn divergent(size: usize, replica: ReplicaId) -> MapState<u64, u64> {
+    MapState::from_versions((0..size as u64).map(|k| match k % 3 {
+        0 => (k, Entry::Occupied(!k), 2, replica),
+        1 => (k, Entry::Tombstoned, 2, replica),
+        _ => (k, Entry::Occupied(!k), 1, replica),
+    }))
+}
+
+fn main() {
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let filter = filter.as_deref();
+
+    for size in SIZES {
+        bench(
+            filter,
+            &format!("map/insert/{size}"),
+            || LWWMap::with_capacity(size),
+            |mut map| {
+                for k in 0..size as u64 {
+                    map.insert(k, k);
+                }
+                map
+            },
+        );
+
+        let map = LWWMap::from(converged(size, LOCAL));
+        let same = converged(size, LOCAL);
+        bench(
+            filter,
+            &format!("map/merge/converged/{size}"),
+            || (map.clone(), same.clone()),
+            |(mut map, other)| {
+                map.merge(other);
+                map
+            },
+        );
+
+        let other = divergent(size, REMOTE);
+        bench(
+            filter,
+            &format!("map/merge/divergent/{size}"),
+            || (map.clone(), other.clone()),
+            |(mut map, other)| {
+                map.merge(other);
+                map
+            },
+        );
+    }
+
+    for size in VALUE_SIZES {
+        let reg = LWWRegister::with_replica(vec![0xC0u8; size], LOCAL);
+        let newer = LWWState::from_parts(Some(vec![0xBAu8; size]), 2, REMOTE)
+            .expect("state has a value and a non-zero seq");
+        bench(
+            filter,
+            &format!("register/merge/{size}"),
+            || (reg.clone(), newer.clone()),
+            |(mut reg, other)| {
+                reg.merge(other);
+                reg
+            },
+        );
+    }
+}
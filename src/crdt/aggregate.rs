@@ -0,0 +1,172 @@
+//! This module defines a read-only view over several replicas of the same CRDT, that answers
+//! reads as if the replicas had been merged together, without merging them
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::crdt::counter::array::ArrayGCounter;
+use crate::crdt::lww::map::LWWMap;
+use crate::crdt::CRDT;
+
+/// A read-only view over borrowed replicas of the same CRDT.
+/// Reads are computed from every replica and return what merging all of them would converge to,
+/// while the replicas are left untouched
+#[derive(Debug, Clone)]
+pub struct AggregateView<'a, C> {
+    replicas: Vec<&'a C>,
+}
+
+impl<'a, C> AggregateView<'a, C> {
+    /// Create a view over `replicas`
+    pub fn new(replicas: impl IntoIterator<Item = &'a C>) -> Self {
+        Self {
+            replicas: replicas.into_iter().collect(),
+        }
+    }
+
+    /// Returns the replicas of this view
+    pub fn replicas(&self) -> &[&'a C] {
+        &self.replicas
+    }
+}
+
+impl<const N: usize> AggregateView<'_, ArrayGCounter<N>> {
+    /// Returns the value that the counters would have once merged together
+    pub fn value(&self) -> u64 {
+        self.replicas
+            .iter()
+            .fold(ArrayGCounter::new(), |mut acc, counter| {
+                acc.merge(counter.take());
+                acc
+            })
+            .value()
+    }
+}
+
+impl<'a, K, V> AggregateView<'a, LWWMap<K, V>>
+where
+    K: Eq + Hash,
+{
+    /// Returns the maps at the most recent epoch, the only ones whose keys survive a merge, see
+    /// [`LWWMap::reset`]
+    fn latest_epoch(&self) -> impl Iterator<Item = &'a LWWMap<K, V>> + '_ {
+        let epoch = self.replicas.iter().map(|map| map.epoch()).max();
+        self.replicas
+            .iter()
+            .copied()
+            .filter(move |map| Some(map.epoch()) == epoch)
+    }
+
+    /// Returns the latest value of the key across every map, see [`LWWMap::get`].
+    /// Concurrent values are resolved with Last-Write-Wins, regardless of the
+    /// [`MergePolicy`](crate::crdt::lww::map::MergePolicy) of the key. Maps from an older epoch
+    /// are ignored
+    pub fn get<Q>(&self, k: &Q) -> Option<&'a V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.latest_epoch()
+            .filter_map(|map| map.register(k))
+            .reduce(|latest, reg| if reg.wins_over(latest) { reg } else { latest })
+            .and_then(|reg| reg.value().get())
+    }
+
+    /// Returns the live entries of the union of every map, with the latest value of every key, in
+    /// arbitrary order. Maps from an older epoch are ignored
+    pub fn entries(&self) -> Vec<(&'a K, &'a V)> {
+        let mut latest = HashMap::new();
+        for (k, reg) in self.latest_epoch().flat_map(|map| map.registers()) {
+            let ours = latest.entry(k).or_insert(reg);
+            if reg.wins_over(ours) {
+                *ours = reg;
+            }
+        }
+
+        latest
+            .into_iter()
+            .filter_map(|(k, reg)| reg.value().get().map(|v| (k, v)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::counter::array::ArrayGCounter;
+    use crate::crdt::lww::map::LWWMap;
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::{converge, CRDT};
+
+    use super::AggregateView;
+
+    #[test]
+    fn aggregate_counters() {
+        let mut counters = [ArrayGCounter::<3>::new(); 3];
+        for (i, counter) in counters.iter_mut().enumerate() {
+            counter
+                .increment(ReplicaId::new(i as u64), 10 * (i as u64 + 1))
+                .unwrap();
+        }
+
+        // The third replica has already seen the writes of the first one
+        let first = counters[0];
+        counters[2].merge(first.take());
+
+        let view = AggregateView::new(&counters);
+        assert_eq!(view.value(), 60);
+        assert_eq!(counters[0].value(), 10);
+        assert_eq!(view.value(), converge(counters).unwrap().value());
+    }
+
+    #[test]
+    fn aggregate_maps() {
+        let mut a = LWWMap::with_replica(ReplicaId::new(1));
+        a.insert("shared", 0xBAD);
+        a.insert("a", 0xA);
+
+        let mut b = a.clone();
+        b.insert("shared", 0xC0FFEE);
+        b.remove("a");
+
+        let mut c = LWWMap::with_replica(ReplicaId::new(3));
+        c.insert("c", 0xC);
+
+        let maps = [a, b, c];
+        let view = AggregateView::new(&maps);
+        assert_eq!(view.get("shared"), Some(&0xC0FFEE));
+        assert_eq!(view.get("a"), None);
+        assert_eq!(maps[0].get("a"), Some(&0xA));
+
+        let mut entries = view.entries();
+        entries.sort();
+        assert_eq!(entries, vec![(&"c", &0xC), (&"shared", &0xC0FFEE)]);
+
+        let merged = converge(maps.clone()).unwrap();
+        let mut expected: Vec<_> = merged.iter().collect();
+        expected.sort();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn aggregate_maps_ignore_older_epochs() {
+        let mut a = LWWMap::with_replica(ReplicaId::new(1));
+        a.insert("kept", 0xBAD);
+        a.insert("dropped", 0xDEAD);
+        for _ in 0..3 {
+            a.insert("kept", 0xBAD);
+        }
+
+        let mut b = LWWMap::with_replica(ReplicaId::new(2));
+        b.reset([("kept", 0xC0FFEE)]);
+
+        let maps = [a, b];
+        let view = AggregateView::new(&maps);
+        assert_eq!(view.get("kept"), Some(&0xC0FFEE));
+        assert_eq!(view.get("dropped"), None);
+        assert_eq!(view.entries(), vec![(&"kept", &0xC0FFEE)]);
+
+        let merged = converge(maps.clone()).unwrap();
+        assert_eq!(merged.iter().collect::<Vec<_>>(), view.entries());
+    }
+}
@@ -0,0 +1,101 @@
+//! This module defines a builder for the CRDTs that break ties between concurrent writes with the
+//! id of the replica that performed them.
+//! The builder can only build once a replica id has been set, which turns forgetting it into a
+//! compile error:
+//!
+//! ```compile_fail
+//! use replixel::crdt::builder::Builder;
+//!
+//! // `register` only exists once `replica` has been called
+//! let reg = Builder::new().register(0xC0FFEE);
+//! ```
+//!
+//! ```
+//! use replixel::crdt::builder::Builder;
+//! use replixel::crdt::replica::ReplicaId;
+//!
+//! let reg = Builder::new().replica(ReplicaId::new(1)).register(0xC0FFEE);
+//! assert_eq!(reg.replica(), ReplicaId::new(1));
+//! ```
+
+use crate::crdt::counter::timed::TimedGCounter;
+use crate::crdt::lww::map::LWWMap;
+use crate::crdt::lww::register::LWWRegister;
+use crate::crdt::replica::ReplicaId;
+
+/// The state of a [`Builder`] whose replica id has not been set yet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WithoutId;
+
+/// The state of a [`Builder`] whose replica id has been set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WithId(ReplicaId);
+
+/// A builder of replica-aware CRDTs, that can only build once its replica id has been set with
+/// [`Builder::replica`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Builder<S> {
+    state: S,
+}
+
+impl Builder<WithoutId> {
+    /// Create a builder without a replica id
+    pub fn new() -> Self {
+        Self { state: WithoutId }
+    }
+
+    /// Set the id of the replica that will own the built CRDTs
+    pub fn replica(self, replica: ReplicaId) -> Builder<WithId> {
+        Builder {
+            state: WithId(replica),
+        }
+    }
+}
+
+impl Builder<WithId> {
+    /// Returns the id of the replica that will own the built CRDTs
+    pub fn replica_id(&self) -> ReplicaId {
+        self.state.0
+    }
+
+    /// Build a register that holds `value`, written by the replica of this builder, see
+    /// [`LWWRegister::with_replica`]
+    pub fn register<T>(self, value: T) -> LWWRegister<T> {
+        LWWRegister::with_replica(value, self.state.0)
+    }
+
+    /// Build an empty map whose local writes are performed by the replica of this builder, see
+    /// [`LWWMap::with_replica`]
+    pub fn map<K, V>(self) -> LWWMap<K, V> {
+        LWWMap::with_replica(self.state.0)
+    }
+
+    /// Build a counter owned by the replica of this builder, with a value of zero
+    pub fn counter(self) -> TimedGCounter {
+        TimedGCounter::new(self.state.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::replica::ReplicaId;
+
+    use super::Builder;
+
+    #[test]
+    fn built_crdts_are_owned_by_replica() {
+        let builder = Builder::new().replica(ReplicaId::new(7));
+        assert_eq!(builder.replica_id(), ReplicaId::new(7));
+
+        let mut map = builder.map();
+        map.insert("key", 0xC0FFEE);
+        assert_eq!(map.replica(), ReplicaId::new(7));
+
+        assert_eq!(builder.register(0xBAD).replica(), ReplicaId::new(7));
+
+        let mut counter = builder.counter();
+        counter.increment_at(3, 1);
+        assert_eq!(counter.value(), 3);
+        assert_eq!(counter.last_update(ReplicaId::new(7)), Some(1));
+    }
+}
@@ -0,0 +1,150 @@
+//! This module defines a grow-only counter for a fixed number of replicas that does not allocate
+
+use std::fmt;
+
+use crate::crdt::replica::ReplicaId;
+use crate::crdt::CRDT;
+
+/// Error returned when a replica id does not fit in the counter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplicaOutOfRange {
+    /// The replica that has been rejected
+    pub replica: ReplicaId,
+
+    /// The number of replicas that the counter can hold
+    pub replicas: usize,
+}
+
+impl fmt::Display for ReplicaOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "replica {} is out of range for a counter of {} replicas",
+            self.replica.get(),
+            self.replicas
+        )
+    }
+}
+
+impl std::error::Error for ReplicaOutOfRange {}
+
+/// A grow-only counter for a cluster of `N` replicas, identified by the ids `0..N`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArrayGCounter<const N: usize> {
+    counts: [u64; N],
+}
+
+impl<const N: usize> ArrayGCounter<N> {
+    /// Create a new counter with a value of zero
+    pub const fn new() -> Self {
+        Self { counts: [0; N] }
+    }
+
+    /// Increment the contribution of `replica` by `by`, saturating at [`u64::MAX`]
+    pub fn increment(&mut self, replica: ReplicaId, by: u64) -> Result<(), ReplicaOutOfRange> {
+        let count = usize::try_from(replica.get())
+            .ok()
+            .and_then(|i| self.counts.get_mut(i))
+            .ok_or(ReplicaOutOfRange {
+                replica,
+                replicas: N,
+            })?;
+
+        *count = count.saturating_add(by);
+        Ok(())
+    }
+
+    /// Returns the value of the counter, saturating at [`u64::MAX`], see [`Self::value_saturating`]
+    pub fn value(&self) -> u64 {
+        self.value_saturating()
+    }
+
+    /// Returns the value of the counter, or [`u64::MAX`] if it does not fit in a `u64`
+    pub fn value_saturating(&self) -> u64 {
+        self.counts.iter().fold(0, |sum, &c| sum.saturating_add(c))
+    }
+
+    /// Returns the value of the counter, or [`None`] if it does not fit in a `u64`
+    pub fn checked_value(&self) -> Option<u64> {
+        self.counts
+            .iter()
+            .try_fold(0u64, |sum, &c| sum.checked_add(c))
+    }
+}
+
+impl<const N: usize> Default for ArrayGCounter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> CRDT for ArrayGCounter<N> {
+    type State = [u64; N];
+
+    fn merge(&mut self, other: Self::State) {
+        for (ours, theirs) in self.counts.iter_mut().zip(other) {
+            *ours = (*ours).max(theirs);
+        }
+    }
+
+    fn take(self) -> Self::State {
+        self.counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::CRDT;
+
+    use super::{ArrayGCounter, ReplicaOutOfRange};
+
+    #[test]
+    fn merge_converges() {
+        let mut a = ArrayGCounter::<4>::new();
+        let mut b = ArrayGCounter::<4>::new();
+
+        a.increment(ReplicaId::new(0), 3).unwrap();
+        b.increment(ReplicaId::new(1), 2).unwrap();
+        b.increment(ReplicaId::new(3), 1).unwrap();
+
+        let (sa, sb) = (a.take(), b.take());
+        a.merge(sb);
+        b.merge(sa);
+
+        // Merging is idempotent
+        a.merge(sb);
+
+        assert_eq!(a, b);
+        assert_eq!(a.value(), 6);
+    }
+
+    #[test]
+    fn increment_out_of_range() {
+        let mut counter = ArrayGCounter::<2>::new();
+
+        assert_eq!(
+            counter.increment(ReplicaId::new(2), 1),
+            Err(ReplicaOutOfRange {
+                replica: ReplicaId::new(2),
+                replicas: 2
+            })
+        );
+        assert_eq!(counter.value(), 0);
+    }
+
+    #[test]
+    fn saturates_near_max() {
+        let mut counter = ArrayGCounter::<2>::new();
+
+        counter.increment(ReplicaId::new(0), u64::MAX - 1).unwrap();
+        counter.increment(ReplicaId::new(0), 5).unwrap();
+        assert_eq!(counter.take()[0], u64::MAX);
+        assert_eq!(counter.checked_value(), Some(u64::MAX));
+
+        counter.increment(ReplicaId::new(1), 1).unwrap();
+        assert_eq!(counter.value(), u64::MAX);
+        assert_eq!(counter.value_saturating(), u64::MAX);
+        assert_eq!(counter.checked_value(), None);
+    }
+}
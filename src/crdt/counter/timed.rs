@@ -0,0 +1,203 @@
+//! This module defines a grow-only counter that also records when every replica last incremented
+//! it
+
+use std::collections::BTreeMap;
+
+use crate::crdt::replica::ReplicaId;
+use crate::crdt::CRDT;
+
+/// The contribution of a single replica to a [`TimedGCounter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedCount {
+    /// The number of increments of the replica
+    pub count: u64,
+
+    /// The timestamp of the last increment of the replica
+    pub last_ts: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimedGCounterState {
+    counts: BTreeMap<ReplicaId, TimedCount>,
+}
+
+/// A grow-only counter that records the timestamp of the last increment of every replica, e.g
+/// to show how stale the contribution of a replica is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedGCounter {
+    replica: ReplicaId,
+
+    state: TimedGCounterState,
+}
+
+impl TimedGCounter {
+    /// Create a new counter owned by `replica` with a value of zero
+    pub fn new(replica: ReplicaId) -> Self {
+        Self {
+            replica,
+            state: TimedGCounterState::default(),
+        }
+    }
+
+    /// Increment the contribution of the replica that owns this counter by `by`, at time `ts`,
+    /// saturating at [`u64::MAX`]
+    pub fn increment_at(&mut self, by: u64, ts: u64) {
+        let count = self.state.counts.entry(self.replica).or_insert(TimedCount {
+            count: 0,
+            last_ts: ts,
+        });
+
+        count.count = count.count.saturating_add(by);
+        count.last_ts = count.last_ts.max(ts);
+    }
+
+    /// Returns the value of the counter, saturating at [`u64::MAX`], see [`Self::value_saturating`]
+    pub fn value(&self) -> u64 {
+        self.value_saturating()
+    }
+
+    /// Returns the value of the counter, or [`u64::MAX`] if it does not fit in a `u64`
+    pub fn value_saturating(&self) -> u64 {
+        self.state
+            .counts
+            .values()
+            .fold(0, |sum, c| sum.saturating_add(c.count))
+    }
+
+    /// Returns the value of the counter, or [`None`] if it does not fit in a `u64`
+    pub fn checked_value(&self) -> Option<u64> {
+        self.state
+            .counts
+            .values()
+            .try_fold(0u64, |sum, c| sum.checked_add(c.count))
+    }
+
+    /// Decommission the replica that owns this counter: merge its state into `other` and move
+    /// its contribution into the contribution of the replica that owns `other`, so that its slot
+    /// is reclaimed while the value of the counter is preserved.
+    ///
+    /// The retired replica id must never be used again: increments performed with it would be
+    /// counted on top of the contribution that has been moved. Retirement is not propagated by
+    /// merges either, a replica that still holds the retired slot brings it back when merged into
+    /// `other`, counting it twice. Every other replica must stop merging with the retired replica
+    /// and drop its slot the same way before exchanging states with `other` again
+    ///
+    /// # Panics
+    ///
+    /// Panics if both counters are owned by the same replica
+    pub fn retire_into(self, other: &mut TimedGCounter) {
+        assert_ne!(
+            self.replica, other.replica,
+            "a replica can not be retired into itself"
+        );
+
+        let retired = self.replica;
+        other.merge(self.state);
+
+        let Some(theirs) = other.state.counts.remove(&retired) else {
+            return;
+        };
+
+        let ours = other
+            .state
+            .counts
+            .entry(other.replica)
+            .or_insert(TimedCount {
+                count: 0,
+                last_ts: theirs.last_ts,
+            });
+        ours.count = ours.count.saturating_add(theirs.count);
+        ours.last_ts = ours.last_ts.max(theirs.last_ts);
+    }
+
+    /// Returns the timestamp of the last increment of `replica`, if it ever incremented the counter
+    pub fn last_update(&self, replica: ReplicaId) -> Option<u64> {
+        self.state.counts.get(&replica).map(|c| c.last_ts)
+    }
+}
+
+impl CRDT for TimedGCounter {
+    type State = TimedGCounterState;
+
+    /// Keep the highest count of every replica along with its timestamp
+    fn merge(&mut self, other: Self::State) {
+        for (replica, theirs) in other.counts {
+            let ours = self.state.counts.entry(replica).or_insert(theirs);
+            if (theirs.count, theirs.last_ts) > (ours.count, ours.last_ts) {
+                *ours = theirs;
+            }
+        }
+    }
+
+    fn take(self) -> Self::State {
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::{CRDTExt, CRDT};
+
+    use super::TimedGCounter;
+
+    #[test]
+    fn merge_keeps_latest_timestamps() {
+        let (ra, rb) = (ReplicaId::new(1), ReplicaId::new(2));
+        let mut a = TimedGCounter::new(ra);
+        let mut b = TimedGCounter::new(rb);
+
+        a.increment_at(2, 100);
+        b.merge(a.clone().take());
+        a.increment_at(1, 250);
+
+        b.increment_at(5, 200);
+
+        let stale = b.clone();
+        a.clone().merge_into(&mut b);
+        b.clone().merge_into(&mut a);
+        stale.merge_into(&mut a);
+
+        assert_eq!(a.clone().take(), b.clone().take());
+        assert_eq!(a.value(), 8);
+        assert_eq!(a.last_update(ra), Some(250));
+        assert_eq!(a.last_update(rb), Some(200));
+        assert_eq!(a.last_update(ReplicaId::new(3)), None);
+    }
+
+    #[test]
+    fn saturates_near_max() {
+        let mut a = TimedGCounter::new(ReplicaId::new(1));
+        a.increment_at(u64::MAX - 1, 1);
+        a.increment_at(5, 2);
+        assert_eq!(a.checked_value(), Some(u64::MAX));
+
+        let mut b = TimedGCounter::new(ReplicaId::new(2));
+        b.increment_at(1, 3);
+        b.merge_into(&mut a);
+
+        assert_eq!(a.value(), u64::MAX);
+        assert_eq!(a.value_saturating(), u64::MAX);
+        assert_eq!(a.checked_value(), None);
+        assert_eq!(a.last_update(ReplicaId::new(1)), Some(2));
+    }
+
+    #[test]
+    fn retire_preserves_value() {
+        let (ra, rb, rc) = (ReplicaId::new(1), ReplicaId::new(2), ReplicaId::new(3));
+        let mut a = TimedGCounter::new(ra);
+        let mut b = TimedGCounter::new(rb);
+        let mut c = TimedGCounter::new(rc);
+
+        a.increment_at(4, 100);
+        b.increment_at(3, 200);
+        c.increment_at(2, 300);
+        c.clone().merge_into(&mut b);
+
+        b.retire_into(&mut a);
+        assert_eq!(a.value(), 9);
+        assert_eq!(a.last_update(rb), None);
+        assert_eq!(a.last_update(ra), Some(200));
+        assert_eq!(a.last_update(rc), Some(300));
+    }
+}
@@ -0,0 +1,108 @@
+//! A hash function with a fixed specification, for the hashes that replicas compare with each
+//! other.
+//!
+//! [`std::hash::DefaultHasher`] is not guaranteed to give the same output across Rust releases, so
+//! replicas built with different toolchains would disagree on any hash it computes
+
+use std::hash::{Hash, Hasher};
+
+/// The 64-bit FNV-1a hash function, see <http://www.isthe.com/chongo/tech/comp/fnv/>.
+/// Integers are hashed as their little-endian bytes, and `usize`/`isize` as 64-bit integers, so that
+/// the output does not depend on the platform either
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
+}
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16)
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32)
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128)
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64)
+    }
+}
+
+/// Returns the [`Fnv1a`] hash of `value`
+pub(crate) fn fnv1a<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = Fnv1a::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::Hasher;
+
+    use super::{fnv1a, Fnv1a};
+
+    #[test]
+    fn matches_reference_vectors() {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = Fnv1a::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+
+        assert_eq!(hash(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(hash(b"a"), 0xAF63_DC4C_8601_EC8C);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_F739_67E8);
+
+        // Integers are hashed as their little-endian bytes, whatever the platform
+        assert_eq!(fnv1a(&0xC0FFEE_u64), hash(&0xC0FFEE_u64.to_le_bytes()));
+        assert_eq!(fnv1a(&0xC0FFEE_usize), fnv1a(&0xC0FFEE_u64));
+    }
+}
@@ -0,0 +1,160 @@
+//! This module defines an append-only log, a CRDT where every replica appends entries and merging
+//! produces the union of all entries in the same total order on every replica
+
+use std::collections::BTreeMap;
+
+use crate::crdt::replica::ReplicaId;
+use crate::crdt::CRDT;
+
+/// Uniquely identifies an entry of the log.
+/// Entries are totally ordered by their logical clock first and by the replica that appended them
+/// to break ties
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntryId {
+    pub clock: u64,
+    pub replica: ReplicaId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrowLogState<T> {
+    entries: BTreeMap<EntryId, T>,
+}
+
+/// A grow-only log of entries
+#[derive(Debug, Clone)]
+pub struct GrowLog<T> {
+    replica: ReplicaId,
+
+    /// The highest logical clock that this log has observed
+    clock: u64,
+
+    state: GrowLogState<T>,
+}
+
+impl<T> GrowLog<T> {
+    /// Create a new, empty log owned by `replica`
+    pub fn new(replica: ReplicaId) -> Self {
+        Self {
+            replica,
+            clock: 0,
+            state: GrowLogState {
+                entries: BTreeMap::new(),
+            },
+        }
+    }
+
+    /// Append a new entry to the log and return its identifier.
+    /// The entry is ordered after every entry that this log has observed so far
+    pub fn append(&mut self, entry: T) -> EntryId {
+        self.clock += 1;
+
+        let id = EntryId {
+            clock: self.clock,
+            replica: self.replica,
+        };
+
+        self.state.entries.insert(id, entry);
+        id
+    }
+
+    /// Returns an iterator over the entries of the log, in total order
+    pub fn iter(&self) -> impl Iterator<Item = (&EntryId, &T)> {
+        self.state.entries.iter()
+    }
+
+    /// Returns a reference to the entry identified by `id`
+    pub fn get(&self, id: &EntryId) -> Option<&T> {
+        self.state.entries.get(id)
+    }
+
+    /// Returns the number of entries in the log
+    pub fn len(&self) -> usize {
+        self.state.entries.len()
+    }
+
+    /// Returns `true` if the log contains no entries
+    pub fn is_empty(&self) -> bool {
+        self.state.entries.is_empty()
+    }
+}
+
+/// Logs are equal if they hold the same entries, whatever replica owns them
+impl<T: PartialEq> PartialEq for GrowLog<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.state == other.state
+    }
+}
+
+impl<T: Eq> Eq for GrowLog<T> {}
+
+impl<T> CRDT for GrowLog<T> {
+    type State = GrowLogState<T>;
+
+    fn merge(&mut self, other: Self::State) {
+        for (id, entry) in other.entries {
+            self.clock = self.clock.max(id.clock);
+            self.state.entries.entry(id).or_insert(entry);
+        }
+    }
+
+    fn take(self) -> Self::State {
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::CRDT;
+
+    use super::GrowLog;
+
+    fn entries<T: Clone>(log: &GrowLog<T>) -> Vec<T> {
+        log.iter().map(|(_, e)| e.clone()).collect()
+    }
+
+    #[test]
+    fn append_in_order() {
+        let mut log = GrowLog::new(ReplicaId::new(1));
+        log.append("first");
+        log.append("second");
+
+        assert_eq!(entries(&log), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn concurrent_appends_interleave_in_the_same_order() {
+        let mut a = GrowLog::new(ReplicaId::new(1));
+        let mut b = GrowLog::new(ReplicaId::new(2));
+
+        a.append("a1");
+        b.append("b1");
+        b.append("b2");
+        a.append("a2");
+
+        assert_converges!(a, b);
+
+        let (sa, sb) = (a.clone().take(), b.clone().take());
+        a.merge(sb);
+        b.merge(sa);
+
+        assert_eq!(entries(&a), vec!["a1", "b1", "a2", "b2"]);
+        assert_eq!(entries(&a), entries(&b));
+
+        // Entries appended after a merge are ordered after everything that has been observed
+        a.append("a3");
+        assert_eq!(entries(&a).last(), Some(&"a3"));
+    }
+
+    #[test]
+    fn merge_deduplicates_entries() {
+        let mut a = GrowLog::new(ReplicaId::new(1));
+        a.append("a1");
+
+        let mut b = GrowLog::new(ReplicaId::new(2));
+        b.merge(a.clone().take());
+        b.merge(a.clone().take());
+
+        assert_eq!(b.len(), 1);
+    }
+}
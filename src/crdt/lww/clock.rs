@@ -0,0 +1,110 @@
+//! Module that defines the default register of the crate, and a builder of registers that lets
+//! the clock be picked once for a whole code base.
+//!
+//! Registers either order their writes with sequence numbers bumped by every write, see
+//! [`LWWRegister`], or with timestamps taken from the system clock, see [`Lww`]. [`Register`] and
+//! [`RegisterBuilder`] default to sequence numbers
+
+use crate::crdt::replica::ReplicaId;
+
+use super::register::LWWRegister;
+use super::timestamped::Lww;
+
+/// The default register of the crate, which orders its writes with sequence numbers
+pub type Register<T> = LWWRegister<T>;
+
+/// The clock of the registers built by a [`RegisterBuilder`] by default: sequence numbers bumped
+/// by every write, see [`LWWRegister`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeqClock;
+
+/// The clock of the registers built by a [`RegisterBuilder`] once [`RegisterBuilder::wall_clock`]
+/// has been called: timestamps taken from the system clock, see [`Lww`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WallClock;
+
+/// A builder of registers that use the clock `C`, a [`SeqClock`] unless another clock is picked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegisterBuilder<C = SeqClock> {
+    replica: ReplicaId,
+
+    clock: C,
+}
+
+impl RegisterBuilder {
+    /// Create a builder of registers that use the default clock, written by an anonymous replica
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<C> RegisterBuilder<C> {
+    /// Set the replica that writes the local values of the built registers
+    pub fn replica(self, replica: ReplicaId) -> Self {
+        Self { replica, ..self }
+    }
+
+    /// Build registers that order their writes with sequence numbers, see [`LWWRegister`]
+    pub fn seq_clock(self) -> RegisterBuilder<SeqClock> {
+        RegisterBuilder {
+            replica: self.replica,
+            clock: SeqClock,
+        }
+    }
+
+    /// Build registers that order their writes with timestamps taken from the system clock, see
+    /// [`Lww`]
+    pub fn wall_clock(self) -> RegisterBuilder<WallClock> {
+        RegisterBuilder {
+            replica: self.replica,
+            clock: WallClock,
+        }
+    }
+}
+
+impl RegisterBuilder<SeqClock> {
+    /// Build a register that holds `value`, see [`LWWRegister::with_replica`]
+    pub fn build<T>(self, value: T) -> Register<T> {
+        LWWRegister::with_replica(value, self.replica)
+    }
+}
+
+impl RegisterBuilder<WallClock> {
+    /// Build a register that holds `value`, stamped with the current time, see
+    /// [`Lww::with_replica`]
+    pub fn build<T>(self, value: T) -> Lww<T> {
+        Lww::with_replica(value, self.replica)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::lww::register::LWWRegister;
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::CRDT;
+
+    use super::{Register, RegisterBuilder};
+
+    #[test]
+    fn registers_default_to_seq_clock() {
+        let reg: Register<_> = RegisterBuilder::new()
+            .replica(ReplicaId::new(3))
+            .build(0xC0FFEE);
+        // The alias is the register ordered by sequence numbers
+        let _: LWWRegister<_> = reg.clone();
+        assert_eq!(reg.seq(), 1);
+        assert_eq!(reg.replica(), ReplicaId::new(3));
+
+        // The wall clock stamps the first write with the current time
+        let lww = RegisterBuilder::new()
+            .replica(ReplicaId::new(3))
+            .wall_clock()
+            .build(0xC0FFEE);
+        let state = CRDT::take(lww);
+        assert!(state.seq() > 1);
+        assert_eq!(state.replica(), ReplicaId::new(3));
+
+        let reg = RegisterBuilder::new().wall_clock().seq_clock().build(0xBAD);
+        assert_eq!(reg.seq(), 1);
+    }
+}
@@ -1,22 +1,33 @@
 //! Module that defines a `Map` of [`LWWRegister`] values
 
 use std::borrow::Borrow;
+use std::cmp::Ordering;
 use std::collections::{hash_map, HashMap};
-use std::hash::Hash;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 
-use crate::crdt::{CRDTExt, CRDT};
+use crate::crdt::hash::{fnv1a, Fnv1a};
+use crate::crdt::replica::ReplicaId;
+use crate::crdt::CRDT;
 
-use super::register::LWWRegister;
+use super::register::{LWWRegister, LWWState, SeqExhausted};
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Entry<V> {
     Occupied(V),
+
+    /// The value has been moved to the trash, it is not visible anymore but can be restored
+    SoftDeleted(V),
+
     Tombstoned,
 }
 
 impl<V> Entry<V> {
     /// Return a reference to the current value that this entry holds
-    /// Return [`None`] if the current entry is [`Self::Tombstoned`]
-    fn get(&self) -> Option<&V> {
+    /// Return [`None`] if the current entry is not [`Self::Occupied`]
+    pub(crate) fn get(&self) -> Option<&V> {
         let Self::Occupied(v) = self else {
             return None;
         };
@@ -25,8 +36,8 @@ impl<V> Entry<V> {
     }
 
     /// Take the entry if the current entry is [`Self::Occupied`]
-    /// Return [`None`] if the current entry is [`Self::Tombstoned`]
-    fn take(self) -> Option<V> {
+    /// Return [`None`] if the current entry is not [`Self::Occupied`]
+    pub(crate) fn take(self) -> Option<V> {
         let Entry::Occupied(v) = self else {
             return None;
         };
@@ -34,120 +45,4526 @@ impl<V> Entry<V> {
         Some(v)
     }
 
-    /// Returns `true` if the current entry is [`Tombstoned`]
-    fn is_tombstoned(&self) -> bool {
+    /// Return a reference to the value that this entry holds, whether it is live or in the trash
+    pub(crate) fn held(&self) -> Option<&V> {
+        match self {
+            Self::Occupied(v) | Self::SoftDeleted(v) => Some(v),
+            Self::Tombstoned => None,
+        }
+    }
+
+    /// Convert the value that this entry holds with `f`, keeping the kind of entry
+    fn map<W>(self, f: impl FnOnce(V) -> W) -> Entry<W> {
+        match self {
+            Self::Occupied(v) => Entry::Occupied(f(v)),
+            Self::SoftDeleted(v) => Entry::SoftDeleted(f(v)),
+            Self::Tombstoned => Entry::Tombstoned,
+        }
+    }
+
+    /// Returns `true` if the current entry is [`Self::Occupied`]
+    pub(crate) fn is_live(&self) -> bool {
+        matches!(self, Self::Occupied(_))
+    }
+
+    /// Returns `true` if the current entry is [`Self::Tombstoned`]
+    pub(crate) fn is_tombstoned(&self) -> bool {
         matches!(self, Self::Tombstoned)
     }
+
+    /// Returns `true` if both entries are live and hold equal values.
+    /// Such entries written concurrently are not a conflict, whichever wins holds the same value
+    fn same_live_value(&self, other: &Self) -> bool
+    where
+        V: PartialEq,
+    {
+        self.get().is_some_and(|v| other.get() == Some(v))
+    }
+
+    /// The precedence of this entry over another entry written with the same sequence number
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Occupied(_) => 0,
+            Self::SoftDeleted(_) => 1,
+            Self::Tombstoned => 2,
+        }
+    }
+}
+
+impl<V> LWWRegister<Entry<V>> {
+    /// Merge the state of another register holding an [`Entry`].
+    ///
+    /// On top of the Last-Write-Wins rule, a tombstone always wins over a soft-deleted value,
+    /// which always wins over a live value, that has been written with the same sequence number.
+    /// This way an insert and a remove that reach the same sequence number on two replicas converge
+    /// to the removal regardless of the direction of the merge
+    pub(crate) fn merge_entry(&mut self, other: LWWState<Entry<V>>) {
+        if self.seq() == other.seq() && other.value().rank() != self.value().rank() {
+            if other.value().rank() > self.value().rank() {
+                *self = LWWRegister::from_state(other);
+            }
+            return;
+        }
+
+        self.merge(other)
+    }
+
+    /// Merge the state of another register holding an [`Entry`], resolving two live values with
+    /// `policy` if any, see [`MergePolicy`].
+    /// With a policy, a live value always wins over a value that is not live
+    fn merge_entry_with(&mut self, other: LWWState<Entry<V>>, policy: Option<&KeyPolicy<V>>) {
+        if let Some(policy) = policy {
+            let (ours, theirs) = match (self.value().get(), other.value().get()) {
+                (Some(ours), Some(theirs)) => (ours, theirs),
+                (Some(_), None) => return,
+                (None, Some(_)) => {
+                    *self = LWWRegister::from_state(other);
+                    return;
+                }
+                (None, None) => return self.merge_entry(other),
+            };
+
+            let ord = match policy.policy {
+                MergePolicy::LastWriteWins => Ordering::Equal,
+                MergePolicy::MaxValue => (policy.cmp)(theirs, ours),
+                MergePolicy::MinValue => (policy.cmp)(ours, theirs),
+                MergePolicy::NeverOverwrite => {
+                    (self.seq(), self.replica()).cmp(&(other.seq(), other.replica()))
+                }
+            };
+
+            match ord {
+                Ordering::Greater => {
+                    *self = LWWRegister::from_state(other);
+                    return;
+                }
+                Ordering::Less => return,
+                Ordering::Equal => {}
+            }
+        }
+
+        self.merge_entry(other)
+    }
+
+    /// Returns the version of the register, compared to detect whether a merge changed it
+    fn version(&self) -> (u64, ReplicaId, u8) {
+        (self.seq(), self.replica(), self.value().rank())
+    }
+
+    /// Returns `true` if this register wins over `other` when they are merged with
+    /// [`Self::merge_entry`]
+    pub(crate) fn wins_over(&self, other: &Self) -> bool {
+        let precedence = |reg: &Self| (reg.seq(), reg.value().rank(), reg.replica());
+        precedence(self) > precedence(other)
+    }
 }
 
+#[derive(Debug, Clone)]
 pub struct MapState<K, V> {
     inner: HashMap<K, LWWRegister<Entry<V>>>,
-}
 
-/// A map of [`LWWRegister`] values
-pub struct LWWMap<K, V> {
-    state: MapState<K, V>,
+    /// The number of times the map has been reset, see [`LWWMap::reset`]
+    epoch: u64,
 }
 
-impl<K, V> LWWMap<K, V> {
-    /// Create a new, empty map
-    pub fn new() -> Self {
-        Self {
-            state: MapState {
-                inner: HashMap::new(),
-            },
-        }
+impl<K, V> MapState<K, V> {
+    /// Create a state from the registers of every key, including the tombstoned ones
+    pub fn from_inner(inner: HashMap<K, LWWRegister<Entry<V>>>) -> Self {
+        Self { inner, epoch: 0 }
     }
-}
 
-impl<K, V> LWWMap<K, V>
-where
-    K: Eq + Hash,
-{
-    /// Returns a reference to the value corresponding to the key.
-    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    /// Create a state from the `(key, entry, seq, replica)` version of every key, including the
+    /// tombstoned ones, without replaying the writes that led to them, e.g to set up fixtures.
+    /// Versions with a sequence number of zero are not valid writes and are skipped. A key that
+    /// appears several times keeps its last version
+    pub fn from_versions(versions: impl IntoIterator<Item = (K, Entry<V>, u64, ReplicaId)>) -> Self
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        K: Eq + Hash,
     {
-        self.state.inner.get(k).and_then(|reg| reg.value().get())
+        let inner = versions
+            .into_iter()
+            .filter_map(|(k, entry, seq, replica)| {
+                let state = LWWState::from_parts(Some(entry), seq, replica).ok()?;
+                Some((k, LWWRegister::from_state(state)))
+            })
+            .collect();
+
+        Self::from_inner(inner)
     }
 
-    /// Inserts a key-value pair into the map.
-    /// If the map did not have this key present, [`None`] is returned.
-    /// If the map did have this key present, the register holding the value is updated, and the old value is returned.
-    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
-        match self.state.inner.entry(k) {
-            hash_map::Entry::Occupied(mut e) => e.get_mut().update(Entry::Occupied(v)).take(),
-            hash_map::Entry::Vacant(e) => {
-                e.insert(LWWRegister::new(Entry::Occupied(v)));
-                None
-            }
-        }
+    /// Returns the number of times the map has been reset
+    pub fn epoch(&self) -> u64 {
+        self.epoch
     }
 
-    pub fn remove<Q>(&mut self, k: &Q) -> Option<V>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        self.state
-            .inner
-            .get_mut(k)
-            .and_then(|e| e.update(Entry::Tombstoned).take())
+    /// Decompose the state into the registers of every key, including the tombstoned ones
+    pub fn into_inner(self) -> HashMap<K, LWWRegister<Entry<V>>> {
+        self.inner
     }
 
-    /// Returns `true` if the map contains a value for the specified key.
-    pub fn contains_key<Q>(&self, k: &Q) -> bool
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        self.state
-            .inner
-            .get(k)
-            .map(|e| !e.value().is_tombstoned())
-            .unwrap_or(false)
+    /// Returns the sequence number of the version of every key, including the tombstoned ones
+    pub fn seqs(&self) -> impl Iterator<Item = (&K, u64)> {
+        self.inner.iter().map(|(k, reg)| (k, reg.seq()))
     }
 }
 
-impl<K, V> CRDT for LWWMap<K, V>
+impl<K, V> MapState<K, V>
 where
     K: Eq + Hash,
 {
-    type State = MapState<K, V>;
+    /// Compute a digest of the versions of every key of this state, including tombstones.
+    /// Two states that hold the same versions have the same digest, regardless of the order in
+    /// which the versions have been written or merged
+    pub fn digest(&self) -> u64 {
+        self.keys_digest().with_epoch(self.epoch)
+    }
 
-    fn merge(&mut self, other: Self::State) {
-        for (k, v) in other.inner {
-            match self.state.inner.entry(k) {
-                hash_map::Entry::Occupied(mut e) => v.merge_into(e.get_mut()),
-                hash_map::Entry::Vacant(e) => {
-                    if let Some(entry) = v.take() {
-                        e.insert(LWWRegister::new(entry));
-                    }
-                }
+    fn keys_digest(&self) -> Digest {
+        self.inner
+            .iter()
+            .fold(Digest::default(), |mut digest, (k, reg)| {
+                digest.add(k, reg);
+                digest
+            })
+    }
+
+    /// Compute what changed between this state and a newer `other` state.
+    /// A key is considered to have changed if its version in `other` is more recent than its
+    /// version in this state
+    pub fn diff<'a>(&'a self, other: &'a Self) -> MapDiff<&'a K, &'a V> {
+        let mut diff = MapDiff {
+            added: Vec::new(),
+            updated: Vec::new(),
+            tombstoned: Vec::new(),
+        };
+
+        for (k, theirs) in &other.inner {
+            let ours = self.inner.get(k);
+            if ours
+                .map(|r| (r.seq(), r.replica()) >= (theirs.seq(), theirs.replica()))
+                .unwrap_or(false)
+            {
+                continue;
+            }
+
+            match (ours.and_then(|r| r.value().get()), theirs.value().get()) {
+                (None, Some(new)) => diff.added.push((k, new)),
+                (Some(old), Some(new)) => diff.updated.push((k, old, new)),
+                (Some(old), None) => diff.tombstoned.push((k, old)),
+                (None, None) => {}
             }
         }
+
+        diff
     }
+}
 
-    fn take(self) -> Self::State {
-        self.state
+impl<K, V> PartialEq for MapState<K, V>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.epoch == other.epoch && self.inner == other.inner
     }
 }
 
-impl<K, V> FromIterator<(K, V)> for LWWMap<K, V>
+impl<K, V> Eq for MapState<K, V>
 where
     K: Eq + Hash,
+    V: Eq,
 {
-    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
-        let iter = iter
-            .into_iter()
-            .map(|(k, v)| (k, LWWRegister::new(Entry::Occupied(v))));
+}
+
+/// The changes between two [`MapState`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct MapDiff<K, V> {
+    /// Keys that became live along with their value
+    pub added: Vec<(K, V)>,
+
+    /// Keys that were live and have been overwritten, along with their old and new value
+    pub updated: Vec<(K, V, V)>,
+
+    /// Keys that were live and have been removed, along with their old value
+    pub tombstoned: Vec<(K, V)>,
+}
+
+/// A summary of the contents of a [`LWWMap`], see [`LWWMap::stats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapStats {
+    /// The number of live entries
+    pub live: usize,
+
+    /// The number of soft-deleted entries, that can still be restored
+    pub trashed: usize,
+
+    /// The number of tombstoned entries that have not been purged yet.
+    /// A large number of tombstones is a hint that the map should be garbage collected
+    pub tombstones: usize,
+
+    /// The highest sequence number of any entry, including tombstones
+    pub max_seq: u64,
+
+    /// The number of entries, including tombstones, whose current version has been written by
+    /// each replica
+    pub writes: HashMap<ReplicaId, usize>,
+}
+
+/// A digest of the versions of the keys of a map, along with the number of live keys and
+/// tombstones.
+/// The digest of every key is combined with a commutative operation, which lets the digest be
+/// maintained incrementally as keys are written.
+/// Keys can be hashed through any of their [`Borrow`] forms, which are required to hash identically.
+/// Versions are hashed with [`Fnv1a`], so that replicas built with different toolchains compute
+/// the same digests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Digest {
+    hash: u64,
+
+    live: usize,
+
+    tombstones: usize,
+}
+
+impl Digest {
+    fn of<K: Hash + ?Sized, V>(k: &K, reg: &LWWRegister<Entry<V>>) -> u64 {
+        let mut hasher = Fnv1a::default();
+        k.hash(&mut hasher);
+        reg.seq().hash(&mut hasher);
+        reg.replica().hash(&mut hasher);
+        reg.value().rank().hash(&mut hasher);
+        hasher.finish()
+    }
 
+    fn add<K: Hash + ?Sized, V>(&mut self, k: &K, reg: &LWWRegister<Entry<V>>) {
+        self.hash = self.hash.wrapping_add(Self::of(k, reg));
+        match reg.value() {
+            Entry::Occupied(_) => self.live += 1,
+            Entry::SoftDeleted(_) => {}
+            Entry::Tombstoned => self.tombstones += 1,
+        }
+    }
+
+    fn remove<K: Hash + ?Sized, V>(&mut self, k: &K, reg: &LWWRegister<Entry<V>>) {
+        self.hash = self.hash.wrapping_sub(Self::of(k, reg));
+        match reg.value() {
+            Entry::Occupied(_) => self.live -= 1,
+            Entry::SoftDeleted(_) => {}
+            Entry::Tombstoned => self.tombstones -= 1,
+        }
+    }
+
+    /// Combine the digest of the keys with the epoch of the map they belong to
+    fn with_epoch(self, epoch: u64) -> u64 {
+        if epoch == 0 {
+            return self.hash;
+        }
+
+        fnv1a(&(self.hash, epoch))
+    }
+}
+
+/// The combined digest of the versions of the keys that fall into a bucket, see
+/// [`LWWMap::key_digests`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct BucketHash(pub u64);
+
+/// Returns the bucket that `k` falls into, out of `buckets`.
+/// When `buckets` is a power of two, the keys of the bucket `i` are split between the buckets `i`
+/// and `i + buckets` out of `2 * buckets`.
+/// Keys are hashed with [`Fnv1a`], so that every replica assigns them to the same bucket
+fn bucket_of<K: Hash + ?Sized>(k: &K, buckets: usize) -> usize {
+    (fnv1a(k) % buckets as u64) as usize
+}
+
+/// A callback that is invoked with the key of every live entry that gets tombstoned
+pub type TombstoneObserver<K> = Box<dyn FnMut(&K) + Send + Sync>;
+
+/// A callback that is invoked with the key and the size of every incoming value that a merge
+/// skipped because it exceeds [`LWWMap::max_value_bytes`]
+pub type OversizedObserver<K> = Box<dyn FnMut(&K, usize) + Send + Sync>;
+
+/// A callback that is invoked with a summary of every merge of a whole state
+pub type MergeObserver = Box<dyn FnMut(&MergeReport) + Send + Sync>;
+
+/// A function that extracts the application-level version of a value, see
+/// [`LWWMap::set_version_extractor`]
+pub type VersionExtractor<V> = Arc<dyn Fn(&V) -> u64 + Send + Sync>;
+
+/// A function that returns whether the tombstone of a key written with a sequence number can be
+/// purged, see [`LWWMap::auto_gc`]
+pub type StabilityCheck<K> = Arc<dyn Fn(&K, u64) -> bool + Send + Sync>;
+
+/// A summary of the merge of a whole state into a [`LWWMap`], see [`LWWMap::on_merge`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// The number of incoming keys that changed the local state
+    pub changed: usize,
+
+    /// The number of incoming keys that did not change the local state
+    pub unchanged: usize,
+
+    /// The number of incoming keys that were written concurrently with the local version, with the
+    /// same sequence number, and had to be resolved by a tie-break.
+    /// Like [`MergeSummary::conflicts_resolved`], this counts equal values written concurrently
+    pub conflicts: usize,
+
+    /// The number of incoming keys that have been skipped because their value exceeds
+    /// [`LWWMap::max_value_bytes`] or has a lower version than the local value, see
+    /// [`LWWMap::set_version_extractor`]
+    pub skipped: usize,
+}
+
+/// A summary of what the merge of a whole state changed in a [`LWWMap`], see
+/// [`LWWMap::merge_summary`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeSummary {
+    /// The number of keys that were absent or tombstoned and became live
+    pub inserted: usize,
+
+    /// The number of live keys whose value has been overwritten
+    pub updated: usize,
+
+    /// The number of keys that have been removed, or moved to the trash, by a more recent removal
+    pub tombstoned: usize,
+
+    /// The number of keys whose incoming version has been written concurrently with the local
+    /// version, with the same sequence number, and had to be resolved by a tie-break.
+    /// Equal live values written concurrently by different replicas are counted as well, since
+    /// values are not compared here. Unlike this count, [`LWWMap::concurrent_keys`] and
+    /// [`LWWMap::merge_capturing_losers`] do not report such keys, as no value is lost
+    pub conflicts_resolved: usize,
+
+    /// The number of incoming keys whose version is not more recent than the local version
+    pub ignored_stale: usize,
+}
+
+/// The outcome of committing the coalesced writes of a [`LWWMap`], see [`LWWMap::commit`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Committed<K> {
+    /// The number of keys that have actually changed
+    pub changed: usize,
+
+    /// The keys whose write has been rejected, along with the reason, in arbitrary order
+    pub rejected: Vec<(K, WriteError)>,
+}
+
+/// Identifies a write to a key of a [`LWWMap`] by the replica that performed it and the sequence
+/// number it reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WriteId {
+    /// The replica that performed the write
+    pub replica: ReplicaId,
+
+    /// The sequence number of the key after the write
+    pub seq: u64,
+}
+
+impl<V> From<&LWWState<Entry<V>>> for WriteId {
+    fn from(state: &LWWState<Entry<V>>) -> Self {
         Self {
-            state: MapState {
-                inner: iter.collect(),
-            },
+            replica: state.replica(),
+            seq: state.seq(),
+        }
+    }
+}
+
+/// A live value that has been discarded by a merge because it was written concurrently with the
+/// value that won, see [`LWWMap::merge_capturing_losers`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict<K, V> {
+    /// The key that has been written concurrently
+    pub key: K,
+
+    /// The value that lost
+    pub value: V,
+
+    /// The write that won
+    pub winner: WriteId,
+
+    /// The write that lost
+    pub loser: WriteId,
+}
+
+/// The progress of a merge performed in chunks, see [`LWWMap::merge_chunked`]
+#[derive(Debug)]
+pub enum MergeProgress<K, V> {
+    /// The whole state has been merged
+    Done,
+
+    /// Part of the state has been merged, the remaining state must be merged by another call
+    Pending(MapState<K, V>),
+}
+
+/// Error returned when writing a value that exceeds [`LWWMap::max_value_bytes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueTooLarge {
+    /// The size of the rejected value, in bytes
+    pub size: usize,
+
+    /// The maximum size of a value, in bytes
+    pub max: usize,
+}
+
+impl fmt::Display for ValueTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "value of {} bytes exceeds the maximum of {} bytes",
+            self.size, self.max
+        )
+    }
+}
+
+impl std::error::Error for ValueTooLarge {}
+
+/// Error returned when a local write to a [`LWWMap`] is rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteError {
+    /// The value exceeds [`LWWMap::max_value_bytes`]
+    TooLarge(ValueTooLarge),
+
+    /// The key is already live in an [append-only](LWWMap::append_only) map
+    AlreadyExists,
+
+    /// Keys can not be removed from an [append-only](LWWMap::append_only) map
+    Immutable,
+
+    /// Keys with a merge policy can not be removed, see [`LWWMap::set_key_policy`]
+    HasPolicy,
+
+    /// The value has a lower application-level version than the live value of the key, see
+    /// [`LWWMap::set_version_extractor`]
+    VersionRegression,
+
+    /// The sequence number of the key reached [`u64::MAX`], see [`SeqExhausted`]
+    SeqExhausted,
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge(e) => e.fmt(f),
+            Self::AlreadyExists => f.write_str("key already exists in an append-only map"),
+            Self::Immutable => f.write_str("keys can not be removed from an append-only map"),
+            Self::HasPolicy => f.write_str("keys with a merge policy can not be removed"),
+            Self::VersionRegression => {
+                f.write_str("value has a lower version than the live value of the key")
+            }
+            Self::SeqExhausted => SeqExhausted.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for WriteError {}
+
+impl From<ValueTooLarge> for WriteError {
+    fn from(e: ValueTooLarge) -> Self {
+        Self::TooLarge(e)
+    }
+}
+
+impl From<SeqExhausted> for WriteError {
+    fn from(_: SeqExhausted) -> Self {
+        Self::SeqExhausted
+    }
+}
+
+/// Error returned when renaming a key of a [`LWWMap`] is rejected, see [`LWWMap::rename`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameError {
+    /// The key to rename is not live
+    NotFound,
+
+    /// Keys can not be removed from an [append-only](LWWMap::append_only) map
+    Immutable,
+
+    /// The key to rename has a merge policy, see [`LWWMap::set_key_policy`]
+    HasPolicy,
+
+    /// The value to rename has a lower application-level version than the live value of the key
+    /// it is renamed to, see [`LWWMap::set_version_extractor`]
+    VersionRegression,
+
+    /// One of the keys reached the highest sequence number, there is no sequence number left to
+    /// write both keys with
+    SeqExhausted,
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("the key to rename is not live"),
+            Self::Immutable => WriteError::Immutable.fmt(f),
+            Self::HasPolicy => WriteError::HasPolicy.fmt(f),
+            Self::VersionRegression => WriteError::VersionRegression.fmt(f),
+            Self::SeqExhausted => f.write_str("the keys to rename reached the highest seq"),
+        }
+    }
+}
+
+impl std::error::Error for RenameError {}
+
+/// The maximum size of the values of a map, along with the function that computes the size of a
+/// value
+struct ValueLimit<V> {
+    max: usize,
+
+    size_of: fn(&V) -> usize,
+}
+
+impl<V> ValueLimit<V> {
+    fn check(&self, v: &V) -> Result<(), ValueTooLarge> {
+        let size = (self.size_of)(v);
+        if size > self.max {
+            return Err(ValueTooLarge {
+                size,
+                max: self.max,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl<V> Clone for ValueLimit<V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V> Copy for ValueLimit<V> {}
+
+/// How the concurrent live values of a key are resolved by a merge, see
+/// [`LWWMap::set_key_policy`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep the most recent value
+    #[default]
+    LastWriteWins,
+
+    /// Keep the largest value, regardless of which one is the most recent
+    MaxValue,
+
+    /// Keep the smallest value, regardless of which one is the most recent
+    MinValue,
+
+    /// Keep the first value that has been written, never overwrite it with a more recent value
+    NeverOverwrite,
+}
+
+/// The merge policy of a key along with the function that compares its values
+struct KeyPolicy<V> {
+    policy: MergePolicy,
+
+    cmp: fn(&V, &V) -> Ordering,
+}
+
+impl<V> Clone for KeyPolicy<V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V> Copy for KeyPolicy<V> {}
+
+/// The policy that purges tombstones automatically, see [`LWWMap::auto_gc`]
+struct AutoGc<K> {
+    ratio: f64,
+
+    is_stable: StabilityCheck<K>,
+
+    /// The number of tombstones that the last purge could not purge
+    retained: usize,
+}
+
+impl<K> Clone for AutoGc<K> {
+    fn clone(&self) -> Self {
+        Self {
+            ratio: self.ratio,
+            is_stable: Arc::clone(&self.is_stable),
+            retained: self.retained,
         }
     }
 }
+
+/// A map of [`LWWRegister`] values
+pub struct LWWMap<K, V> {
+    state: MapState<K, V>,
+
+    digest: Digest,
+
+    limit: Option<ValueLimit<V>>,
+
+    /// Local writes that have been coalesced and not committed yet
+    pending: HashMap<K, Entry<V>>,
+
+    /// The keys that are not merged with [`MergePolicy::LastWriteWins`]
+    policies: HashMap<K, KeyPolicy<V>>,
+
+    /// The replica that performs the local writes, see [`Self::with_replica`]
+    replica: ReplicaId,
+
+    append_only: bool,
+
+    /// Whether local writes are checked with [`Self::debug_assert_valid`], see
+    /// [`Self::set_validate_writes`]
+    validate_writes: bool,
+
+    auto_gc: Option<AutoGc<K>>,
+
+    version_of: Option<VersionExtractor<V>>,
+
+    observers: Vec<TombstoneObserver<K>>,
+
+    oversized_observers: Vec<OversizedObserver<K>>,
+
+    merge_observers: Vec<MergeObserver>,
+}
+
+impl<K, V> LWWMap<K, V> {
+    /// Create a new, empty map
+    pub fn new() -> Self {
+        Self {
+            state: MapState {
+                inner: HashMap::new(),
+                epoch: 0,
+            },
+            digest: Digest::default(),
+            limit: None,
+            pending: HashMap::new(),
+            policies: HashMap::new(),
+            replica: ReplicaId::default(),
+            append_only: false,
+            validate_writes: false,
+            auto_gc: None,
+            version_of: None,
+            observers: Vec::new(),
+            oversized_observers: Vec::new(),
+            merge_observers: Vec::new(),
+        }
+    }
+
+    /// Create a new, empty map that can hold at least `capacity` keys without reallocating, see
+    /// [`Self::capacity`]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            state: MapState {
+                inner: HashMap::with_capacity(capacity),
+                epoch: 0,
+            },
+            ..Self::new()
+        }
+    }
+
+    /// Create a new, empty map whose local writes are performed by `replica`.
+    /// Maps created with [`Self::new`] write as an anonymous replica: two of them that write the
+    /// same key with the same sequence number, e.g by inserting a key that is new to both of them,
+    /// can not tell their writes apart and do not converge. Giving every replica its own id lets a
+    /// merge detect such concurrent writes, report them as conflicts, see
+    /// [`MergeSummary::conflicts_resolved`] and [`Self::merge_capturing_losers`], and resolve them
+    /// by keeping the value written by the highest replica id
+    pub fn with_replica(replica: ReplicaId) -> Self {
+        Self {
+            replica,
+            ..Self::new()
+        }
+    }
+
+    /// Returns a copy of this map whose local writes are performed by `replica`, see
+    /// [`Self::with_replica`].
+    /// This is a cheap way to get several replicas that start from the same state and then diverge
+    /// independently. Like [`Clone`], observers are not carried over to the fork
+    pub fn fork(&self, replica: ReplicaId) -> Self
+    where
+        K: Clone,
+        V: Clone,
+    {
+        Self {
+            replica,
+            ..self.clone()
+        }
+    }
+
+    /// Overwrite `dst` with a copy of this map, like [`Clone::clone_from`], but keep the allocation
+    /// of `dst`: its keys are cleared without releasing its capacity, then every register of this
+    /// map is copied, including the tombstones and their seqs.
+    /// The observers registered on `dst` are kept, the observers of this map are not copied
+    pub fn clone_into(&self, dst: &mut Self)
+    where
+        K: Eq + Hash + Clone,
+        V: Clone,
+    {
+        dst.state.inner.clear();
+        dst.state.inner.extend(
+            self.state
+                .inner
+                .iter()
+                .map(|(k, reg)| (k.clone(), reg.clone())),
+        );
+        dst.state.epoch = self.state.epoch;
+        dst.digest = self.digest;
+        dst.limit = self.limit;
+        dst.pending.clone_from(&self.pending);
+        dst.policies.clone_from(&self.policies);
+        dst.replica = self.replica;
+        dst.append_only = self.append_only;
+        dst.validate_writes = self.validate_writes;
+        dst.auto_gc.clone_from(&self.auto_gc);
+        dst.version_of.clone_from(&self.version_of);
+        dst.debug_assert_valid();
+    }
+
+    /// Returns the replica that performs the local writes of this map
+    pub fn replica(&self) -> ReplicaId {
+        self.replica
+    }
+
+    /// Create a new, empty map whose values can not exceed `max` bytes, as computed by `size_of`.
+    /// Local writes of a larger value are rejected, see [`Self::try_insert`], and larger incoming
+    /// values are skipped by merges, see [`Self::on_oversized`]
+    pub fn with_max_value_bytes(max: usize, size_of: fn(&V) -> usize) -> Self {
+        Self {
+            limit: Some(ValueLimit { max, size_of }),
+            ..Self::new()
+        }
+    }
+
+    /// Create a new, empty map where keys can only be added.
+    /// Local writes to a key that is already live and local removals are rejected, see
+    /// [`Self::try_insert`] and [`Self::try_remove`], while merges still resolve concurrent writes
+    /// to the same key with Last-Write-Wins.
+    /// The other local removals, such as [`Self::remove_many`] or [`Self::reset`], panic
+    pub fn append_only() -> Self {
+        Self {
+            append_only: true,
+            ..Self::new()
+        }
+    }
+
+    /// Returns `true` if keys can only be added to this map
+    pub fn is_append_only(&self) -> bool {
+        self.append_only
+    }
+
+    /// Check the internal invariants of the map after every local write and every merge of a
+    /// single key, like [`Self::debug_assert_valid`], if `enabled` and debug assertions are
+    /// enabled.
+    /// Every check walks the whole map, which makes every write *O(n)*: this is meant for tests and
+    /// debugging sessions. Merges of a whole state are checked regardless, see
+    /// [`Self::debug_assert_valid`]
+    pub fn set_validate_writes(&mut self, enabled: bool) {
+        self.validate_writes = enabled;
+    }
+
+    /// Panics if this map is append-only
+    fn assert_mutable(&self) {
+        if self.append_only {
+            panic!("{}", WriteError::Immutable);
+        }
+    }
+
+    /// Returns the maximum size of a value in bytes, if any
+    pub fn max_value_bytes(&self) -> Option<usize> {
+        self.limit.map(|limit| limit.max)
+    }
+
+    /// Purge tombstones automatically once there are more than `threshold_ratio` tombstones per
+    /// live key, see [`Self::gc`].
+    /// The ratio is checked after every local removal and every merge of a whole state, and only
+    /// the tombstones for which `is_stable` returns `true`, given their key and their sequence
+    /// number, are purged. `is_stable` typically reads a
+    /// [`StabilityTracker`](crate::crdt::stability::StabilityTracker) shared with the replication.
+    ///
+    /// Checking the ratio does not walk the map, only purging does. The tombstones that a purge
+    /// could not purge are not looked at again until there are twice as many tombstones
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold_ratio` is negative or NaN
+    pub fn auto_gc(
+        &mut self,
+        threshold_ratio: f64,
+        is_stable: impl Fn(&K, u64) -> bool + Send + Sync + 'static,
+    ) {
+        assert!(
+            threshold_ratio >= 0.0,
+            "the tombstone ratio must not be negative"
+        );
+
+        self.auto_gc = Some(AutoGc {
+            ratio: threshold_ratio,
+            is_stable: Arc::new(is_stable),
+            retained: 0,
+        });
+    }
+
+    /// Fence every key with the application-level version of its values, as returned by `f`.
+    ///
+    /// A live value can then only be overwritten by a value with the same or a higher version:
+    /// [`Self::try_insert`] rejects a lower version, and merges skip it even if it has been
+    /// written with a higher sequence number. Removals are not fenced.
+    ///
+    /// Rejecting writes of a higher sequence number breaks convergence unless the versions grow
+    /// with the sequence numbers, as two replicas that saw the writes in a different order can
+    /// keep different values
+    pub fn set_version_extractor(&mut self, f: impl Fn(&V) -> u64 + Send + Sync + 'static) {
+        self.version_of = Some(Arc::new(f));
+    }
+
+    fn check_size(&self, v: &V) -> Result<(), ValueTooLarge> {
+        self.limit.map_or(Ok(()), |limit| limit.check(v))
+    }
+
+    /// Returns the digest of the versions of every key of this map, see [`MapState::digest`].
+    /// The digest is maintained as the map is written to, this is *O(1)*
+    pub fn digest(&self) -> u64 {
+        self.digest.with_epoch(self.state.epoch)
+    }
+
+    /// Returns the number of times the map has been reset, see [`Self::reset`]
+    pub fn epoch(&self) -> u64 {
+        self.state.epoch
+    }
+
+    /// Returns a reference to the state of the map, see [`CRDT::take`]
+    pub fn state(&self) -> &MapState<K, V> {
+        &self.state
+    }
+
+    /// Register a callback that will be invoked with the key of every live entry that gets
+    /// tombstoned, either by a local removal or by a merge.
+    /// It is also invoked with the key of every live entry that a new epoch drops, see
+    /// [`Self::reset`]
+    pub fn on_tombstone(&mut self, observer: impl FnMut(&K) + Send + Sync + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Register a callback that will be invoked with the key and the size of every incoming value
+    /// that a merge skipped because it exceeds [`Self::max_value_bytes`]
+    pub fn on_oversized(&mut self, observer: impl FnMut(&K, usize) + Send + Sync + 'static) {
+        self.oversized_observers.push(Box::new(observer));
+    }
+
+    /// Register a callback that will be invoked with a summary of every merge of a whole state.
+    /// Merges of single keys, such as [`Self::merge_key`], are not reported
+    pub fn on_merge(&mut self, observer: impl FnMut(&MergeReport) + Send + Sync + 'static) {
+        self.merge_observers.push(Box::new(observer));
+    }
+}
+
+impl<K, V> fmt::Debug for LWWMap<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LWWMap")
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Cloning a map does not carry its observers over to the clone
+impl<K, V> Clone for LWWMap<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            digest: self.digest,
+            limit: self.limit,
+            pending: self.pending.clone(),
+            policies: self.policies.clone(),
+            replica: self.replica,
+            append_only: self.append_only,
+            validate_writes: self.validate_writes,
+            auto_gc: self.auto_gc.clone(),
+            version_of: self.version_of.clone(),
+            observers: Vec::new(),
+            oversized_observers: Vec::new(),
+            merge_observers: Vec::new(),
+        }
+    }
+}
+
+impl<K, V> PartialEq for LWWMap<K, V>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.state == other.state
+    }
+}
+
+impl<K, V> Eq for LWWMap<K, V>
+where
+    K: Eq + Hash,
+    V: Eq,
+{
+}
+
+impl<K, V> From<MapState<K, V>> for LWWMap<K, V>
+where
+    K: Eq + Hash,
+{
+    fn from(state: MapState<K, V>) -> Self {
+        Self {
+            digest: state.keys_digest(),
+            state,
+            ..Self::new()
+        }
+    }
+}
+
+impl<K, V> Default for LWWMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> LWWMap<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.state.inner.get(k).and_then(|reg| reg.value().get())
+    }
+
+    /// Returns the value corresponding to the key projected through `f`, see [`Self::get`]
+    pub fn get_as<'a, Q, W>(&'a self, k: &Q, f: impl FnOnce(&'a V) -> W) -> Option<W>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(k).map(f)
+    }
+
+    /// Set how the concurrent live values of `k` are resolved by a merge.
+    /// Every replica must use the same policy for a key, otherwise they would not converge.
+    ///
+    /// A key with a policy other than [`MergePolicy::LastWriteWins`] can not be removed: its local
+    /// removals are rejected, and a merge always keeps a live value over a removal. Resolving
+    /// removals by recency and live values by `V::cmp` would make the outcome depend on the order
+    /// of the merges
+    pub fn set_key_policy(&mut self, k: K, policy: MergePolicy)
+    where
+        V: Ord,
+    {
+        if policy == MergePolicy::LastWriteWins {
+            self.policies.remove(&k);
+            return;
+        }
+
+        self.policies.insert(
+            k,
+            KeyPolicy {
+                policy,
+                cmp: V::cmp,
+            },
+        );
+    }
+
+    /// Returns how the concurrent live values of `k` are resolved by a merge
+    pub fn key_policy<Q>(&self, k: &Q) -> MergePolicy
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.policies
+            .get(k)
+            .map_or(MergePolicy::LastWriteWins, |p| p.policy)
+    }
+
+    /// Returns an iterator over the registers of every key, including the tombstoned ones
+    pub(crate) fn registers(&self) -> impl Iterator<Item = (&K, &LWWRegister<Entry<V>>)> {
+        self.state.inner.iter()
+    }
+
+    /// Returns the register of the key, including when it is tombstoned
+    pub(crate) fn register<Q>(&self, k: &Q) -> Option<&LWWRegister<Entry<V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.state.inner.get(k)
+    }
+
+    /// Returns the sequence number of the key, including when it is tombstoned
+    pub(crate) fn seq_of<Q>(&self, k: &Q) -> Option<u64>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.state.inner.get(k).map(|reg| reg.seq())
+    }
+
+    /// Inserts a key-value pair into the map.
+    /// If the map did not have this key present, [`None`] is returned.
+    /// If the map did have this key present, the register holding the value is updated, and the old value is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the write is rejected, see [`Self::try_insert`]
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        match self.try_insert(k, v) {
+            Ok(old) => old,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Inserts a key-value pair into the map, see [`Self::insert`].
+    /// Returns an error and leaves the map untouched if the value exceeds
+    /// [`Self::max_value_bytes`], if the key is already live in an append-only map, or if the
+    /// sequence number of the key is exhausted
+    pub fn try_insert(&mut self, k: K, v: V) -> Result<Option<V>, WriteError> {
+        self.check_insert(&k, &v)?;
+
+        let (digest, replica) = (&mut self.digest, self.replica);
+        let old = match self.state.inner.entry(k) {
+            hash_map::Entry::Occupied(mut e) => {
+                digest.remove(e.key(), e.get());
+                let old = e
+                    .get_mut()
+                    .try_update_by(Entry::Occupied(v), replica)
+                    .expect("seq is not exhausted");
+                digest.add(e.key(), e.get());
+                old.take()
+            }
+            hash_map::Entry::Vacant(e) => {
+                let reg = LWWRegister::with_replica(Entry::Occupied(v), replica);
+                digest.add(e.key(), &reg);
+                e.insert(reg);
+                None
+            }
+        };
+
+        self.assert_valid_after_write();
+        Ok(old)
+    }
+
+    /// Returns the error that [`Self::try_insert`] would return for writing `v` to `k`, if any
+    fn check_insert(&self, k: &K, v: &V) -> Result<(), WriteError> {
+        self.check_size(v)?;
+        if self.regresses(k, v) {
+            return Err(WriteError::VersionRegression);
+        }
+
+        match self.state.inner.get(k) {
+            Some(reg) if self.append_only && reg.value().is_live() => {
+                Err(WriteError::AlreadyExists)
+            }
+            Some(reg) if reg.seq() == u64::MAX => Err(WriteError::SeqExhausted),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key, inserting the value returned by
+    /// `f` if the map does not have this key present.
+    /// A tombstoned key is considered absent, and is written again with the value returned by `f`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the write is rejected, see [`Self::try_get_or_insert_with`]
+    pub fn get_or_insert_with(&mut self, k: K, f: impl FnOnce() -> V) -> &V {
+        match self.try_get_or_insert_with(k, f) {
+            Ok(v) => v,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key, inserting the value returned by
+    /// `f` if the map does not have this key present, see [`Self::get_or_insert_with`].
+    /// Returns an error and leaves the map untouched if the value returned by `f` exceeds
+    /// [`Self::max_value_bytes`], or if the sequence number of the key is exhausted
+    pub fn try_get_or_insert_with(
+        &mut self,
+        k: K,
+        f: impl FnOnce() -> V,
+    ) -> Result<&V, WriteError> {
+        self.assert_valid_after_write();
+
+        let limit = self.limit;
+        let f = move || {
+            let v = f();
+            limit.map_or(Ok(()), |limit| limit.check(&v))?;
+            Ok::<_, WriteError>(v)
+        };
+
+        let (digest, replica) = (&mut self.digest, self.replica);
+        let reg = match self.state.inner.entry(k) {
+            hash_map::Entry::Occupied(mut e) => {
+                if !e.get().value().is_live() {
+                    let v = f()?;
+                    digest.remove(e.key(), e.get());
+                    let written = e.get_mut().try_update_by(Entry::Occupied(v), replica);
+                    digest.add(e.key(), e.get());
+                    written?;
+                }
+                e.into_mut()
+            }
+            hash_map::Entry::Vacant(e) => {
+                let reg = LWWRegister::with_replica(Entry::Occupied(f()?), replica);
+                digest.add(e.key(), &reg);
+                e.insert(reg)
+            }
+        };
+
+        Ok(reg.value().get().expect("entry is live"))
+    }
+
+    /// Returns a guard to the value corresponding to the key, inserting [`V::default`] if the map
+    /// does not have this key present.
+    /// A tombstoned key is considered absent, and is written again with the default value.
+    /// The first mutable access through the guard is a write that bumps the sequence number of the
+    /// key, whether the value is actually changed or not.
+    /// A value written through the guard is checked against [`Self::max_value_bytes`] and the
+    /// version fence, see [`Self::set_version_extractor`], when the guard is dropped. A rejected
+    /// value is rolled back, along with its version, to the value the key held before the guard
+    /// first wrote it
+    ///
+    /// # Panics
+    ///
+    /// Panics if the default value exceeds [`Self::max_value_bytes`], if the value is mutated in an
+    /// append-only map, if the sequence number of the key is exhausted, or when the guard is
+    /// dropped if the value written through it has been rejected
+    ///
+    /// [`V::default`]: Default::default
+    pub fn entry_or_default(&mut self, k: K) -> ValueMut<'_, K, V>
+    where
+        V: Default + Clone,
+    {
+        self.assert_valid_after_write();
+
+        let limit = self.limit;
+        let default = move || {
+            let v = V::default();
+            if let Some(Err(e)) = limit.map(|limit| limit.check(&v)) {
+                panic!("{e}");
+            }
+            v
+        };
+
+        let Self {
+            state,
+            digest,
+            limit,
+            replica,
+            append_only,
+            version_of,
+            ..
+        } = self;
+
+        let entry = match state.inner.entry(k) {
+            hash_map::Entry::Occupied(mut e) => {
+                if !e.get().value().is_live() {
+                    digest.remove(e.key(), e.get());
+                    e.get_mut().update_by(Entry::Occupied(default()), *replica);
+                    digest.add(e.key(), e.get());
+                }
+                e
+            }
+            hash_map::Entry::Vacant(e) => {
+                let reg = LWWRegister::with_replica(Entry::Occupied(default()), *replica);
+                digest.add(e.key(), &reg);
+                e.insert_entry(reg)
+            }
+        };
+
+        ValueMut {
+            entry,
+            digest,
+            limit: *limit,
+            version_of,
+            replica: *replica,
+            append_only: *append_only,
+            written: false,
+            original: None,
+        }
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key was previously live.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map is append-only or if the key has a merge policy, see [`Self::try_remove`]
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.try_remove(k) {
+            Ok(old) => old,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Removes a key from the map, see [`Self::remove`].
+    /// Returns an error and leaves the map untouched if the map is append-only, if the key has a
+    /// merge policy, or if the sequence number of the key is exhausted
+    pub fn try_remove<Q>(&mut self, k: &Q) -> Result<Option<V>, WriteError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.append_only {
+            return Err(WriteError::Immutable);
+        }
+        if self.policies.contains_key(k) {
+            return Err(WriteError::HasPolicy);
+        }
+
+        let replica = self.replica;
+        let Self {
+            state,
+            digest,
+            observers,
+            ..
+        } = self;
+
+        if observers.is_empty() {
+            let Some(reg) = state.inner.get_mut(k) else {
+                return Ok(None);
+            };
+
+            digest.remove(k, reg);
+            let old = reg.try_update_by(Entry::Tombstoned, replica);
+            digest.add(k, reg);
+            let old = old?.take();
+            self.maybe_gc();
+            self.assert_valid_after_write();
+            return Ok(old);
+        }
+
+        // The observers need the owned key, temporarily take the entry out of the map to get it
+        let Some((k, mut reg)) = state.inner.remove_entry(k) else {
+            return Ok(None);
+        };
+        digest.remove(&k, &reg);
+        let old = reg.try_update_by(Entry::Tombstoned, replica);
+        digest.add(&k, &reg);
+        if old.as_ref().is_ok_and(Entry::is_live) {
+            observers.iter_mut().for_each(|observer| observer(&k));
+        }
+
+        state.inner.insert(k, reg);
+        let old = old?.take();
+        self.maybe_gc();
+        self.assert_valid_after_write();
+        Ok(old)
+    }
+
+    /// Move the value of the live key `from` to the key `to`, overwriting the value of `to` if any.
+    ///
+    /// Both keys are written with the same sequence number, one more than the highest sequence
+    /// number of the two keys. This is not atomic: the tombstone of `from` and the value of `to`
+    /// are merged independently, like two writes, and each of them can lose to a concurrent write:
+    /// - a concurrent write to `from` with a higher sequence number wins over the tombstone, the
+    ///   map then holds a value under both keys
+    /// - a concurrent write to `from` with the same or a lower sequence number loses to the
+    ///   tombstone and is silently lost, `to` holds the value that was read before the write
+    /// - two concurrent renames of `from` to different keys both keep the value, under both keys
+    /// - a concurrent write to `to` is resolved with Last-Write-Wins, as any other write
+    ///
+    /// Returns an error and leaves the map untouched if `from` is not live, if the map is
+    /// append-only, if `from` has a merge policy, if the value regresses the version of `to`, see
+    /// [`Self::set_version_extractor`], or if one of the keys reached [`u64::MAX`] as its sequence
+    /// number
+    pub fn rename(&mut self, from: K, to: K) -> Result<(), RenameError> {
+        if self.append_only {
+            return Err(RenameError::Immutable);
+        }
+        if self.policies.contains_key(&from) {
+            return Err(RenameError::HasPolicy);
+        }
+        let Some(v) = self.get(&from) else {
+            return Err(RenameError::NotFound);
+        };
+        if from == to {
+            return Ok(());
+        }
+        if self.regresses(&to, v) {
+            return Err(RenameError::VersionRegression);
+        }
+
+        let seq = self
+            .seq_of(&from)
+            .max(self.seq_of(&to))
+            .unwrap_or(0)
+            .checked_add(1)
+            .ok_or(RenameError::SeqExhausted)?;
+        let replica = self.replica;
+        let v = self
+            .write_entry(&from, |reg| {
+                reg.update_with_seq_by(Entry::Tombstoned, seq, replica)
+                    .expect("seq is greater than the seq of the key")
+                    .take()
+            })
+            .expect("key is live");
+
+        let (digest, replica) = (&mut self.digest, self.replica);
+        match self.state.inner.entry(to) {
+            hash_map::Entry::Occupied(mut e) => {
+                digest.remove(e.key(), e.get());
+                e.get_mut()
+                    .update_with_seq_by(Entry::Occupied(v), seq, replica)
+                    .expect("seq is greater than the seq of the key");
+                digest.add(e.key(), e.get());
+            }
+            hash_map::Entry::Vacant(e) => {
+                let state = LWWState::from_parts(Some(Entry::Occupied(v)), seq, replica)
+                    .expect("seq is not zero");
+                let reg = LWWRegister::from_state(state);
+                digest.add(e.key(), &reg);
+                e.insert(reg);
+            }
+        }
+
+        self.assert_valid_after_write();
+        Ok(())
+    }
+
+    /// Write the register of `k` with `write`, which returns [`None`] to leave it untouched.
+    /// Keeps the digest up to date and notifies the observers if the key stopped being live
+    fn write_entry<Q, R>(
+        &mut self,
+        k: &Q,
+        write: impl FnOnce(&mut LWWRegister<Entry<V>>) -> Option<R>,
+    ) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Self {
+            state,
+            digest,
+            observers,
+            ..
+        } = self;
+
+        // The observers need the owned key, temporarily take the entry out of the map to get it
+        let (k, mut reg) = state.inner.remove_entry(k)?;
+        let was_live = reg.value().is_live();
+
+        digest.remove(&k, &reg);
+        let written = write(&mut reg);
+        digest.add(&k, &reg);
+
+        if was_live && !reg.value().is_live() {
+            observers.iter_mut().for_each(|observer| observer(&k));
+        }
+
+        state.inner.insert(k, reg);
+        self.maybe_gc();
+        self.assert_valid_after_write();
+        written
+    }
+
+    /// Move the value of a live key to the trash and return `true`, or return `false` if the key
+    /// is not live, if it has a merge policy or if its sequence number is exhausted.
+    /// A value in the trash is not visible anymore but can be restored with [`Self::restore`],
+    /// until it is permanently removed with [`Self::hard_remove`]
+    pub fn soft_remove<Q>(&mut self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.assert_mutable();
+        if self.policies.contains_key(k) {
+            return false;
+        }
+        let replica = self.replica;
+
+        self.write_entry(k, |reg| {
+            if !reg.value().is_live() {
+                return None;
+            }
+
+            reg.update_with(replica, |entry| {
+                if let Entry::Occupied(v) = std::mem::replace(entry, Entry::Tombstoned) {
+                    *entry = Entry::SoftDeleted(v);
+                }
+            })
+            .ok()
+        })
+        .is_some()
+    }
+
+    /// Restore the value of a key that has been moved to the trash and return `true`, or return
+    /// `false` if the key is not in the trash or if its sequence number is exhausted
+    pub fn restore<Q>(&mut self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.assert_mutable();
+        let replica = self.replica;
+
+        self.write_entry(k, |reg| {
+            if !matches!(reg.value(), Entry::SoftDeleted(_)) {
+                return None;
+            }
+
+            reg.update_with(replica, |entry| {
+                if let Entry::SoftDeleted(v) = std::mem::replace(entry, Entry::Tombstoned) {
+                    *entry = Entry::Occupied(v);
+                }
+            })
+            .ok()
+        })
+        .is_some()
+    }
+
+    /// Permanently remove a key, whether it is live or in the trash, and return the value it held.
+    /// A key that has a merge policy or whose sequence number is exhausted is left untouched
+    pub fn hard_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.assert_mutable();
+        if self.policies.contains_key(k) {
+            return None;
+        }
+        let replica = self.replica;
+
+        self.write_entry(k, |reg| {
+            if reg.value().is_tombstoned() {
+                return None;
+            }
+
+            match reg.try_update_by(Entry::Tombstoned, replica).ok()? {
+                Entry::Occupied(v) | Entry::SoftDeleted(v) => Some(v),
+                Entry::Tombstoned => None,
+            }
+        })
+    }
+
+    /// Returns a reference to the value corresponding to a key that has been moved to the trash
+    pub fn get_trashed<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.state.inner.get(k)?.value() {
+            Entry::SoftDeleted(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Removes every key of `keys` from the map and return how many of them were live.
+    /// This behaves like calling [`Self::remove`] for every key, but looks every key up only once.
+    /// Keys that have a merge policy or whose sequence number is exhausted are left untouched
+    pub fn remove_many(&mut self, keys: impl IntoIterator<Item = K>) -> usize {
+        self.assert_mutable();
+
+        let Self {
+            state,
+            digest,
+            replica,
+            policies,
+            observers,
+            ..
+        } = self;
+
+        let mut removed = 0;
+        for k in keys {
+            if policies.contains_key(&k) {
+                continue;
+            }
+            let hash_map::Entry::Occupied(mut e) = state.inner.entry(k) else {
+                continue;
+            };
+
+            digest.remove(e.key(), e.get());
+            let old = e.get_mut().try_update_by(Entry::Tombstoned, *replica);
+            digest.add(e.key(), e.get());
+
+            if old.is_ok_and(|old| old.is_live()) {
+                observers.iter_mut().for_each(|observer| observer(e.key()));
+                removed += 1;
+            }
+        }
+
+        self.maybe_gc();
+        self.assert_valid_after_write();
+        removed
+    }
+
+    /// Buffer the insertion of a key-value pair until the next [`Self::commit`].
+    /// Writing the same key several times before committing only keeps the last write, which
+    /// bumps the sequence number of the key once instead of once per write.
+    /// Uncommitted writes are not visible to reads nor to merges
+    ///
+    /// # Panics
+    ///
+    /// Panics if the write is rejected, see [`Self::try_insert_coalesced`]
+    pub fn insert_coalesced(&mut self, k: K, v: V) {
+        if let Err(e) = self.try_insert_coalesced(k, v) {
+            panic!("{e}");
+        }
+    }
+
+    /// Buffer the insertion of a key-value pair until the next [`Self::commit`], see
+    /// [`Self::insert_coalesced`].
+    /// Returns an error and buffers nothing if [`Self::try_insert`] would reject the write right
+    /// away. The write can still be rejected when it is committed, e.g if a merge made the key
+    /// live in an append-only map in the meantime
+    pub fn try_insert_coalesced(&mut self, k: K, v: V) -> Result<(), WriteError> {
+        self.check_insert(&k, &v)?;
+
+        self.pending.insert(k, Entry::Occupied(v));
+        Ok(())
+    }
+
+    /// Buffer the removal of a key until the next [`Self::commit`], see [`Self::insert_coalesced`]
+    pub fn remove_coalesced(&mut self, k: K) {
+        self.assert_mutable();
+
+        self.pending.insert(k, Entry::Tombstoned);
+    }
+
+    /// Apply the last coalesced write of every key, bumping the sequence number of each written
+    /// key once.
+    /// Returns how many keys have actually changed, removing a key that the map does not hold is
+    /// not a write, along with the writes that have been rejected, see [`Self::try_insert`] and
+    /// [`Self::try_remove`]. A rejected write leaves its key untouched and does not prevent the
+    /// other writes from being applied
+    pub fn commit(&mut self) -> Committed<K> {
+        let pending = std::mem::take(&mut self.pending);
+
+        let mut committed = Committed {
+            changed: 0,
+            rejected: Vec::new(),
+        };
+        for (k, entry) in pending {
+            let written = match entry {
+                Entry::Occupied(v) => {
+                    if let Err(e) = self.check_insert(&k, &v) {
+                        committed.rejected.push((k, e));
+                        continue;
+                    }
+
+                    self.try_insert(k, v).expect("write has been checked");
+                    true
+                }
+                Entry::SoftDeleted(_) => self.soft_remove(&k),
+                // Removals only write a tombstone over a key that the map holds
+                Entry::Tombstoned => match self.try_remove(&k) {
+                    Ok(_) => self.state.inner.contains_key(&k),
+                    Err(e) => {
+                        committed.rejected.push((k, e));
+                        continue;
+                    }
+                },
+            };
+
+            if written {
+                committed.changed += 1;
+            }
+        }
+
+        committed
+    }
+
+    /// Removes every live key that matches `predicate` and return how many have been removed.
+    /// Every removal is a regular tombstone, which makes this the way to remove a whole group of
+    /// composite keys, such as every `(user, field)` key of a given user.
+    /// Keys that have a merge policy or whose sequence number is exhausted are left untouched
+    pub fn remove_prefix(&mut self, predicate: impl Fn(&K) -> bool) -> usize {
+        self.assert_mutable();
+
+        let Self {
+            state,
+            digest,
+            replica,
+            policies,
+            observers,
+            ..
+        } = self;
+
+        let mut removed = 0;
+        for (k, reg) in state.inner.iter_mut() {
+            if !reg.value().is_live() || policies.contains_key(k) || !predicate(k) {
+                continue;
+            }
+
+            digest.remove(k, reg);
+            let written = reg.try_update_by(Entry::Tombstoned, *replica).is_ok();
+            digest.add(k, reg);
+
+            if written {
+                observers.iter_mut().for_each(|observer| observer(k));
+                removed += 1;
+            }
+        }
+
+        self.maybe_gc();
+        self.assert_valid_after_write();
+        removed
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.state
+            .inner
+            .get(k)
+            .map(|e| e.value().is_live())
+            .unwrap_or(false)
+    }
+
+    /// Returns the values corresponding to `keys`, in the same order, see [`Self::get`]
+    pub fn get_many<Q>(&self, keys: &[&Q]) -> Vec<Option<&V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        keys.iter().map(|k| self.get(k)).collect()
+    }
+
+    /// Returns `true` if the map contains a value for every key of `keys`, see
+    /// [`Self::contains_key`]
+    pub fn contains_all<Q>(&self, keys: &[&Q]) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        keys.iter().all(|k| self.contains_key(k))
+    }
+
+    /// Returns an iterator over the live entries of the map, in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.state
+            .inner
+            .iter()
+            .filter_map(|(k, reg)| reg.value().get().map(|v| (k, v)))
+    }
+
+    /// Returns an iterator over the live keys of the map, in arbitrary order
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over every key of the map, in arbitrary order, including the keys that
+    /// are tombstoned or in the trash
+    pub fn all_keys(&self) -> impl Iterator<Item = &K> {
+        self.state.inner.keys()
+    }
+
+    /// Returns an iterator over the tombstoned keys of the map, in arbitrary order
+    pub fn tombstoned_keys(&self) -> impl Iterator<Item = &K> {
+        self.state
+            .inner
+            .iter()
+            .filter(|(_, reg)| reg.value().is_tombstoned())
+            .map(|(k, _)| k)
+    }
+
+    /// Returns a new map with the values transformed by `f`, keeping the version of every key, the
+    /// tombstones and the epoch, so that the new map can still be merged with other maps derived
+    /// the same way.
+    /// Values in the trash are transformed as well. The new map is written by the same replica but
+    /// does not carry over the configuration of this map, such as its observers or its key policies
+    pub fn map_values<W>(&self, f: impl Fn(&V) -> W) -> LWWMap<K, W>
+    where
+        K: Clone,
+    {
+        let inner = self
+            .state
+            .inner
+            .iter()
+            .map(|(k, reg)| {
+                let entry = match reg.value() {
+                    Entry::Occupied(v) => Entry::Occupied(f(v)),
+                    Entry::SoftDeleted(v) => Entry::SoftDeleted(f(v)),
+                    Entry::Tombstoned => Entry::Tombstoned,
+                };
+                let state = LWWState::from_parts(Some(entry), reg.seq(), reg.replica())
+                    .expect("parts come from a valid state");
+                (k.clone(), LWWRegister::from_state(state))
+            })
+            .collect();
+
+        LWWMap {
+            replica: self.replica,
+            ..LWWMap::from(MapState {
+                inner,
+                epoch: self.state.epoch,
+            })
+        }
+    }
+
+    /// Returns the live entries of the map sorted by key, skipping the first `skip` entries and
+    /// returning at most `take` of them, e.g to page through a large map.
+    /// Like [`Self::iter_sorted`], this sorts the live entries first
+    pub fn window(&self, skip: usize, take: usize) -> Vec<(&K, &V)>
+    where
+        K: Ord,
+    {
+        self.iter_sorted().skip(skip).take(take).collect()
+    }
+
+    /// Returns the number of keys the map can hold without reallocating.
+    /// Tombstoned keys take a slot just like live keys, until they are purged by [`Self::gc`]
+    pub fn capacity(&self) -> usize {
+        self.state.inner.capacity()
+    }
+
+    /// Reserve capacity for at least `additional` more keys, see [`Self::capacity`]
+    pub fn reserve(&mut self, additional: usize) {
+        self.state.inner.reserve(additional);
+    }
+
+    /// Compute a summary of the contents of the map, in a single pass over its entries
+    pub fn stats(&self) -> MapStats {
+        self.state
+            .inner
+            .values()
+            .fold(MapStats::default(), |mut stats, reg| {
+                match reg.value() {
+                    Entry::Occupied(_) => stats.live += 1,
+                    Entry::SoftDeleted(_) => stats.trashed += 1,
+                    Entry::Tombstoned => stats.tombstones += 1,
+                }
+
+                stats.max_seq = stats.max_seq.max(reg.seq());
+                *stats.writes.entry(reg.replica()).or_insert(0) += 1;
+                stats
+            })
+    }
+
+    /// Split the keys of the map, including tombstones, into `buckets` buckets and return the
+    /// digest of the versions of the keys of every bucket.
+    /// Two replicas whose bucket digests differ hold different versions of at least one key of
+    /// that bucket
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buckets` is zero
+    pub fn key_digests(&self, buckets: usize) -> Vec<BucketHash> {
+        assert!(buckets > 0, "keys must be split into at least one bucket");
+
+        let mut digests = vec![Digest::default(); buckets];
+        for (k, reg) in &self.state.inner {
+            digests[bucket_of(k, buckets)].add(k, reg);
+        }
+
+        digests.into_iter().map(|d| BucketHash(d.hash)).collect()
+    }
+
+    /// Narrow down the local keys whose version differs from a remote replica, by exchanging
+    /// bucket digests with it.
+    ///
+    /// Starting from a single bucket, the number of buckets doubles at every level up to
+    /// `2^depth`, and only the buckets whose digest differs are split further. `remote` is asked
+    /// for the digests of a subset of buckets, out of a number of buckets, and must answer with
+    /// the [`Self::key_digests`] of the remote replica for those buckets, in the same order.
+    ///
+    /// The returned keys are candidates: they share their last bucket with a differing key. Keys
+    /// that are only known by the remote replica are not returned, the remote replica finds them
+    /// by running the same reconciliation
+    pub fn diverging_keys(
+        &self,
+        depth: u32,
+        mut remote: impl FnMut(usize, &[usize]) -> Vec<BucketHash>,
+    ) -> Vec<&K> {
+        let mut candidates = vec![0];
+        let mut buckets = 1;
+        for level in 0..=depth {
+            buckets = 1 << level;
+            if level > 0 {
+                candidates = candidates
+                    .into_iter()
+                    .flat_map(|b| [b, b + buckets / 2])
+                    .collect();
+            }
+
+            let ours = self.key_digests(buckets);
+            let theirs = remote(buckets, &candidates);
+            candidates = candidates
+                .into_iter()
+                .zip(theirs)
+                .filter(|&(b, theirs)| ours[b] != theirs)
+                .map(|(b, _)| b)
+                .collect();
+
+            if candidates.is_empty() {
+                return Vec::new();
+            }
+        }
+
+        self.state
+            .inner
+            .keys()
+            .filter(|k| candidates.contains(&bucket_of(*k, buckets)))
+            .collect()
+    }
+
+    /// Authoritatively replace the contents of the map with `contents` and start a new epoch.
+    /// Once merged, the reset wins wholesale over every write of the replicas that have not
+    /// observed it, including writes made concurrently with the reset. Every key that is not part
+    /// of `contents` is dropped, without leaving a tombstone
+    pub fn reset(&mut self, contents: impl IntoIterator<Item = (K, V)>) {
+        self.assert_mutable();
+
+        let dropped = self.start_epoch(self.state.epoch + 1);
+        for (k, v) in contents {
+            self.insert(k, v);
+        }
+        self.notify_dropped(dropped);
+    }
+
+    /// Drop the contents of the map to start the epoch `epoch` and return the dropped entries,
+    /// see [`Self::notify_dropped`]
+    fn start_epoch(&mut self, epoch: u64) -> HashMap<K, LWWRegister<Entry<V>>> {
+        self.state.epoch = epoch;
+        self.digest = Digest::default();
+        if let Some(auto_gc) = &mut self.auto_gc {
+            auto_gc.retained = 0;
+        }
+        std::mem::take(&mut self.state.inner)
+    }
+
+    /// Notify the tombstone observers of every live entry dropped by a new epoch that is not live
+    /// in the new epoch
+    fn notify_dropped(&mut self, dropped: HashMap<K, LWWRegister<Entry<V>>>) {
+        for (k, reg) in dropped {
+            if reg.value().is_live() && !self.contains_key(&k) {
+                self.observers.iter_mut().for_each(|observer| observer(&k));
+            }
+        }
+    }
+
+    /// Returns an iterator over the live entries of the map whose value matches `pred`, in
+    /// arbitrary order
+    pub fn iter_matching(&self, pred: impl Fn(&V) -> bool) -> impl Iterator<Item = (&K, &V)> {
+        self.iter().filter(move |(_, v)| pred(v))
+    }
+
+    /// Returns an iterator over the live entries of the map with their value projected through
+    /// `f`, in arbitrary order.
+    /// Values are projected lazily, as the iterator is advanced
+    pub fn iter_as<'a, W: 'a>(
+        &'a self,
+        f: impl Fn(&'a V) -> W,
+    ) -> impl Iterator<Item = (&'a K, W)> {
+        self.iter().map(move |(k, v)| (k, f(v)))
+    }
+
+    /// Returns an iterator over the live entries of the map whose value can be projected by `f`,
+    /// along with the projection, in arbitrary order.
+    /// This is typically used to select a single variant of an enum, and borrow its content:
+    /// `map.iter_variant(|v| match v { Shape::Circle(r) => Some(r), _ => None })`
+    pub fn iter_variant<'a, W: ?Sized + 'a>(
+        &'a self,
+        f: impl Fn(&'a V) -> Option<&'a W>,
+    ) -> impl Iterator<Item = (&'a K, &'a W)> {
+        self.iter().filter_map(move |(k, v)| f(v).map(|w| (k, w)))
+    }
+
+    /// Returns an iterator over the live entries of the map, sorted by key.
+    /// The live entries are collected and sorted first, this is *O(n log n)*. See
+    /// [`OrderedLWWMap`](super::ordered::OrderedLWWMap) for a map that keeps its keys sorted
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&K, &V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_unstable_by_key(|(k, _)| *k);
+        entries.into_iter()
+    }
+
+    /// Merge the state of a single key from another replica and return whether the local state
+    /// changed.
+    /// This lets a replica reconcile a single key on demand rather than the whole state
+    pub fn merge_key(&mut self, k: K, incoming: LWWState<Entry<V>>) -> bool {
+        let changed = self.merge_one(k, incoming);
+        self.assert_valid_after_write();
+        changed
+    }
+
+    /// Merge the state of a single key like [`Self::merge_key`], without checking the invariants
+    /// of the map, which the merges of a whole state check once they are done
+    fn merge_one(&mut self, k: K, incoming: LWWState<Entry<V>>) -> bool {
+        if !self.admit(&k, &incoming) {
+            return false;
+        }
+
+        let Self {
+            state,
+            digest,
+            policies,
+            observers,
+            ..
+        } = self;
+
+        match state.inner.entry(k) {
+            hash_map::Entry::Occupied(mut e) => {
+                let (was_live, version) = (e.get().value().is_live(), e.get().version());
+                let policy = policies.get(e.key());
+                digest.remove(e.key(), e.get());
+                e.get_mut().merge_entry_with(incoming, policy);
+                digest.add(e.key(), e.get());
+
+                if was_live && !e.get().value().is_live() {
+                    observers.iter_mut().for_each(|observer| observer(e.key()));
+                }
+
+                e.get().version() != version
+            }
+            hash_map::Entry::Vacant(e) => {
+                let reg = LWWRegister::from_state(incoming);
+                digest.add(e.key(), &reg);
+                e.insert(reg);
+                true
+            }
+        }
+    }
+
+    /// Apply an update of a single key received from `replica` and return whether the local state
+    /// changed.
+    /// A `value` of [`None`] is a removal of the key. An update with a sequence number of zero is
+    /// not a valid write and is ignored
+    pub fn apply_remote_update(
+        &mut self,
+        k: K,
+        value: Option<V>,
+        seq: u64,
+        replica: ReplicaId,
+    ) -> bool {
+        let entry = Some(value.map_or(Entry::Tombstoned, Entry::Occupied));
+        let Ok(incoming) = LWWState::from_parts(entry, seq, replica) else {
+            return false;
+        };
+
+        self.merge_key(k, incoming)
+    }
+
+    /// Merge a stream of `(key, entry, seq, replica)` versions from another replica and return
+    /// whether the local state changed.
+    /// This behaves like merging the equivalent [`MapState`] without building it first. Versions
+    /// with a sequence number of zero are not valid writes and are ignored
+    pub fn merge_entries(
+        &mut self,
+        entries: impl IntoIterator<Item = (K, Entry<V>, u64, ReplicaId)>,
+    ) -> bool {
+        let changed = entries
+            .into_iter()
+            .fold(false, |changed, (k, entry, seq, replica)| {
+                let Ok(incoming) = LWWState::from_parts(Some(entry), seq, replica) else {
+                    return changed;
+                };
+
+                self.merge_one(k, incoming) || changed
+            });
+
+        self.debug_assert_valid();
+        changed
+    }
+
+    /// Returns the keys that have been written concurrently in this map and in `other`, without
+    /// merging anything: the keys whose versions have the same sequence number but have been
+    /// written by different replicas, or are of a different kind, e.g a value and a tombstone.
+    /// Such keys are resolved by a tie-break when merged, see [`Self::merge_capturing_losers`].
+    /// Keys that hold equal live values on both sides are not reported, there is nothing to resolve.
+    /// Replicas can only be told apart if every replica writes with its own id, see
+    /// [`Self::with_replica`].
+    /// No key is reported for a state from another epoch, the newest epoch wins wholesale
+    pub fn concurrent_keys(&self, other: &MapState<K, V>) -> Vec<K>
+    where
+        K: Clone,
+        V: PartialEq,
+    {
+        if other.epoch != self.state.epoch {
+            return Vec::new();
+        }
+
+        other
+            .inner
+            .iter()
+            .filter(|(k, theirs)| {
+                self.state.inner.get(*k).is_some_and(|ours| {
+                    ours.seq() == theirs.seq()
+                        && ours.version() != theirs.version()
+                        && !ours.value().same_live_value(theirs.value())
+                })
+            })
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+
+    /// Merge the state of another replica like [`CRDT::merge`] and return the live values that
+    /// have been discarded because they were written concurrently, with the same sequence number,
+    /// as the value that won.
+    /// This surfaces the values that Last-Write-Wins would otherwise silently drop, along with the
+    /// replicas that wrote both sides, so that a user can pick one of them.
+    /// Equal values written concurrently are merged silently, since no value is lost.
+    /// The winner is the version that [`CRDT::merge`] keeps, which follows the policy of the key,
+    /// if any, see [`Self::set_key_policy`].
+    /// Replicas can only be told apart if every replica writes with its own id, see
+    /// [`Self::with_replica`].
+    /// A state from another epoch is merged like [`CRDT::merge`] and no value is returned, the
+    /// newest epoch wins wholesale
+    pub fn merge_capturing_losers(&mut self, other: MapState<K, V>) -> Vec<Conflict<K, V>>
+    where
+        K: Clone,
+        V: Clone + PartialEq,
+    {
+        if other.epoch != self.state.epoch {
+            self.merge_summary(other);
+            return Vec::new();
+        }
+
+        let mut losers = Vec::new();
+        for (k, reg) in other.inner {
+            let incoming = CRDT::take(reg);
+            let ours = self.state.inner.get(&k).filter(|ours| {
+                ours.seq() == incoming.seq()
+                    && ours.version()
+                        != (incoming.seq(), incoming.replica(), incoming.value().rank())
+                    && !ours.value().same_live_value(incoming.value())
+            });
+            let Some((ours_id, ours_value)) = ours.map(|ours| {
+                let id = WriteId {
+                    replica: ours.replica(),
+                    seq: ours.seq(),
+                };
+                (id, ours.value().get().cloned())
+            }) else {
+                self.merge_one(k, incoming);
+                continue;
+            };
+
+            // The merge itself resolves the conflict, which applies the policy of the key, if any.
+            // The incoming version won if the local register changed
+            let (theirs_id, theirs_value) =
+                (WriteId::from(&incoming), incoming.value().get().cloned());
+            let (winner, loser, lost) = if self.merge_one(k.clone(), incoming) {
+                (theirs_id, ours_id, ours_value)
+            } else {
+                (ours_id, theirs_id, theirs_value)
+            };
+
+            losers.extend(lost.map(|value| Conflict {
+                key: k,
+                value,
+                winner,
+                loser,
+            }));
+        }
+
+        self.debug_assert_valid();
+        losers
+    }
+
+    /// Merge the state of another replica like [`CRDT::merge`] and return a summary of what changed
+    pub fn merge_summary(&mut self, other: MapState<K, V>) -> MergeSummary {
+        let mut report = MergeReport::default();
+        let mut summary = MergeSummary::default();
+
+        if other.epoch < self.state.epoch {
+            report.unchanged = other.inner.len();
+            summary.ignored_stale = other.inner.len();
+            self.merge_observers
+                .iter_mut()
+                .for_each(|observer| observer(&report));
+            return summary;
+        }
+
+        let dropped = (other.epoch > self.state.epoch).then(|| self.start_epoch(other.epoch));
+
+        for (k, v) in other.inner {
+            let v = CRDT::take(v);
+            if !self.admit(&k, &v) {
+                report.skipped += 1;
+                continue;
+            }
+
+            let transition = match self.state.inner.entry(k) {
+                hash_map::Entry::Occupied(mut e) => {
+                    let (was_live, ours) = (e.get().value().is_live(), e.get().version());
+                    if ours.0 == v.seq() && ours != (v.seq(), v.replica(), v.value().rank()) {
+                        report.conflicts += 1;
+                    }
+
+                    let policy = self.policies.get(e.key());
+                    self.digest.remove(e.key(), e.get());
+                    e.get_mut().merge_entry_with(v, policy);
+                    self.digest.add(e.key(), e.get());
+
+                    if e.get().version() == ours {
+                        report.unchanged += 1;
+                        summary.ignored_stale += 1;
+                        continue;
+                    }
+
+                    if was_live && !e.get().value().is_live() {
+                        self.observers
+                            .iter_mut()
+                            .for_each(|observer| observer(e.key()));
+                    }
+
+                    (was_live, e.get().value().is_live())
+                }
+                hash_map::Entry::Vacant(e) => {
+                    let reg = LWWRegister::from_state(v);
+                    self.digest.add(e.key(), &reg);
+                    let reg = e.insert(reg);
+                    (false, reg.value().is_live())
+                }
+            };
+
+            report.changed += 1;
+            match transition {
+                (_, false) => summary.tombstoned += 1,
+                (true, true) => summary.updated += 1,
+                (false, true) => summary.inserted += 1,
+            }
+        }
+
+        if let Some(dropped) = dropped {
+            self.notify_dropped(dropped);
+        }
+
+        summary.conflicts_resolved = report.conflicts;
+        self.merge_observers
+            .iter_mut()
+            .for_each(|observer| observer(&report));
+
+        self.maybe_gc();
+        self.debug_assert_valid();
+        summary
+    }
+
+    /// Merge a borrowed state of another replica like [`CRDT::merge`].
+    /// Only the incoming entries that can change the map are cloned, the others are skipped. With
+    /// values that are cheap to clone, such as `Arc<T>`, the winning values end up shared with
+    /// `other` instead of being copied
+    pub fn merge_ref(&mut self, other: &MapState<K, V>)
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let inner = match other.epoch.cmp(&self.state.epoch) {
+            Ordering::Less => HashMap::new(),
+            Ordering::Greater => other.inner.clone(),
+            Ordering::Equal => other
+                .inner
+                .iter()
+                .filter(|(k, theirs)| match self.state.inner.get(*k) {
+                    Some(ours) if self.policies.contains_key(*k) => {
+                        ours.version() != theirs.version()
+                    }
+                    Some(ours) => theirs.wins_over(ours),
+                    None => true,
+                })
+                .map(|(k, reg)| (k.clone(), reg.clone()))
+                .collect(),
+        };
+
+        self.merge_summary(MapState {
+            inner,
+            epoch: other.epoch,
+        });
+    }
+
+    /// Merge the state of another replica, keeping the value that `cmp` ranks higher for the keys
+    /// that are live on both sides, regardless of their sequence numbers. Values that `cmp` ranks
+    /// equal, and keys that are not live on both sides, are merged like [`CRDT::merge`].
+    ///
+    /// This is meant for values where the most complete one should win, such as a partially-filled
+    /// record and a fully-filled one. When the value that `cmp` ranks higher is not the most recent
+    /// one, it is written again by this replica, with a sequence number above both versions, so
+    /// that a replica that merges this one with [`CRDT::merge`] keeps it as well. Such a rewrite is
+    /// a regular local write: a more recent write or removal of the key on another replica still
+    /// wins over it, regardless of `cmp`, and the value is only kept over the versions this
+    /// replica has merged with `merge_by`. If the sequence number of the key is exhausted, the key
+    /// is merged like [`CRDT::merge`].
+    ///
+    /// Unlike [`CRDT::merge`], the outcome depends on the order of the merges: a richer value that
+    /// lost to a more recent removal is gone, and is not kept over a poorer value merged later.
+    /// Since every version is still a regular write, replicas converge once they exchange their
+    /// states with [`CRDT::merge`], but not necessarily to the richest value.
+    /// States from another epoch are merged like [`CRDT::merge`]
+    pub fn merge_by(&mut self, other: MapState<K, V>, cmp: impl Fn(&V, &V) -> Ordering) {
+        if other.epoch != self.state.epoch {
+            self.merge_summary(other);
+            return;
+        }
+
+        for (k, reg) in other.inner {
+            let incoming = CRDT::take(reg);
+            if !self.admit(&k, &incoming) {
+                continue;
+            }
+
+            let ord = match (incoming.value().get(), self.get(&k)) {
+                (Some(theirs), Some(ours)) => cmp(theirs, ours),
+                _ => Ordering::Equal,
+            };
+
+            if ord == Ordering::Equal {
+                self.merge_one(k, incoming);
+                continue;
+            }
+
+            let reg = self.state.inner.get_mut(&k).expect("key is live");
+            let theirs_are_latest =
+                (incoming.seq(), incoming.replica()) > (reg.seq(), reg.replica());
+            let seq = reg.seq().max(incoming.seq()).checked_add(1);
+            let (Some(seq), false) = (seq, theirs_are_latest == (ord == Ordering::Greater)) else {
+                // Last-Write-Wins already keeps the value that `cmp` ranks higher, or there is no
+                // sequence number left to write it again
+                self.merge_one(k, incoming);
+                continue;
+            };
+
+            self.digest.remove(&k, reg);
+            let (theirs, ..) = incoming.into_parts();
+            let value = match ord {
+                Ordering::Greater => theirs,
+                _ => std::mem::replace(reg.value_mut(), Entry::Tombstoned),
+            };
+            reg.update_with_seq_by(value, seq, self.replica)
+                .expect("seq is greater than the seq of the key");
+            self.digest.add(&k, reg);
+        }
+
+        self.debug_assert_valid();
+    }
+
+    /// Merge the state of another replica whose values have another type, converting every value
+    /// with `convert` before merging it like [`CRDT::merge`].
+    /// Converted values keep the version they have been written with, which lets replicas that run
+    /// different versions of a schema be merged during a rolling upgrade
+    pub fn merge_converted<W>(&mut self, other: MapState<K, W>, convert: impl Fn(W) -> V) {
+        let inner = other
+            .inner
+            .into_iter()
+            .map(|(k, reg)| {
+                let (entry, seq, replica) = CRDT::take(reg).into_parts();
+                let state = LWWState::from_parts(Some(entry.map(&convert)), seq, replica)
+                    .expect("parts come from a valid state");
+                (k, LWWRegister::from_state(state))
+            })
+            .collect();
+
+        self.merge_summary(MapState {
+            inner,
+            epoch: other.epoch,
+        });
+    }
+
+    /// Merge at most `budget` keys of the state of another replica and return the remaining state,
+    /// if any, so that a large merge can be interleaved with other work.
+    /// Merging a state chunk by chunk, until [`MergeProgress::Done`] is returned, has the same
+    /// result as merging it at once
+    pub fn merge_chunked(
+        &mut self,
+        mut other: MapState<K, V>,
+        budget: usize,
+    ) -> MergeProgress<K, V> {
+        let chunk = MapState {
+            inner: other
+                .inner
+                .extract_if(|_, _| true)
+                .take(budget.max(1))
+                .collect(),
+            epoch: other.epoch,
+        };
+
+        self.merge(chunk);
+
+        if other.inner.is_empty() {
+            return MergeProgress::Done;
+        }
+
+        MergeProgress::Pending(other)
+    }
+
+    /// Returns `true` if `v` has a lower application-level version than the live value of `k`, see
+    /// [`Self::set_version_extractor`]
+    fn regresses(&self, k: &K, v: &V) -> bool {
+        let (Some(version_of), Some(ours)) = (&self.version_of, self.get(k)) else {
+            return false;
+        };
+
+        version_of(v) < version_of(ours)
+    }
+
+    /// Returns `true` if the `incoming` state of `k` fits in [`Self::max_value_bytes`], otherwise
+    /// notify the oversized observers.
+    /// Returns `false` as well if the incoming value is a version regression, see
+    /// [`Self::set_version_extractor`]
+    fn admit(&mut self, k: &K, incoming: &LWWState<Entry<V>>) -> bool {
+        if let Some(v) = incoming.value().get() {
+            if self.regresses(k, v) {
+                return false;
+            }
+        }
+
+        let Some(Err(e)) = incoming.value().held().map(|v| self.check_size(v)) else {
+            return true;
+        };
+
+        self.oversized_observers
+            .iter_mut()
+            .for_each(|observer| observer(k, e.size));
+        false
+    }
+
+    /// Check the internal invariants of the map and panic if one of them does not hold, like
+    /// [`Self::check_valid`], only if debug assertions are enabled.
+    ///
+    /// This is called after every operation that already walks a whole state: the merges of a
+    /// whole state, including [`Self::merge_entries`], [`Self::gc`] and [`Self::clone_into`].
+    /// Local writes and merges of a single key are only checked if enabled with
+    /// [`Self::set_validate_writes`]
+    pub fn debug_assert_valid(&self) {
+        if cfg!(debug_assertions) {
+            self.check_valid();
+        }
+    }
+
+    /// Check the internal invariants of the map after a local write or a merge of a single key, if
+    /// enabled with [`Self::set_validate_writes`], and always in the tests of this crate.
+    /// The writes that hand out a borrow of the map, such as [`Self::entry_or_default`], check it
+    /// before writing instead
+    fn assert_valid_after_write(&self) {
+        if cfg!(test) || (cfg!(debug_assertions) && self.validate_writes) {
+            self.check_valid();
+        }
+    }
+
+    /// Check the internal invariants of the map and panic if one of them does not hold:
+    /// - every key has a sequence number of at least one
+    /// - the incremental digest matches the digest recomputed from every key
+    ///
+    /// This walks the whole map
+    pub fn check_valid(&self) {
+        assert!(
+            self.state.inner.values().all(|reg| reg.seq() > 0),
+            "invalid map: a key has a sequence number of zero"
+        );
+        assert_eq!(
+            self.digest,
+            self.state.keys_digest(),
+            "invalid map: the digest does not match the keys"
+        );
+    }
+
+    /// Purge the tombstones for which `is_stable` returns `true`, given their key and their
+    /// sequence number, and return how many have been purged.
+    /// A tombstone must only be purged once every replica has observed it, or a more recent version
+    /// of its key, otherwise a stale value could be resurrected by a later merge. Use
+    /// [`StabilityTracker::is_stable`](crate::crdt::stability::StabilityTracker::is_stable) to
+    /// only purge such tombstones
+    pub fn gc(&mut self, is_stable: impl Fn(&K, u64) -> bool) -> usize {
+        let Self { state, digest, .. } = self;
+
+        let len = state.inner.len();
+        state.inner.retain(|k, reg| {
+            let purge = reg.value().is_tombstoned() && is_stable(k, reg.seq());
+            if purge {
+                digest.remove(k, reg);
+            }
+            !purge
+        });
+
+        let purged = len - state.inner.len();
+        self.debug_assert_valid();
+        purged
+    }
+
+    /// Purge the stable tombstones of [`Self::auto_gc`] if they exceed its ratio
+    fn maybe_gc(&mut self) {
+        let Some(auto_gc) = &self.auto_gc else {
+            return;
+        };
+
+        let Digest {
+            live, tombstones, ..
+        } = self.digest;
+        if tombstones as f64 <= auto_gc.ratio * live as f64 || tombstones < 2 * auto_gc.retained {
+            return;
+        }
+
+        let is_stable = Arc::clone(&auto_gc.is_stable);
+        self.gc(|k, seq| is_stable(k, seq));
+
+        let retained = self.digest.tombstones;
+        if let Some(auto_gc) = &mut self.auto_gc {
+            auto_gc.retained = retained;
+        }
+    }
+}
+
+/// A guard to a live value of a [`LWWMap`] that records a write on the first mutable access, see
+/// [`LWWMap::entry_or_default`]
+pub struct ValueMut<'a, K: Hash, V> {
+    entry: hash_map::OccupiedEntry<'a, K, LWWRegister<Entry<V>>>,
+
+    digest: &'a mut Digest,
+
+    limit: Option<ValueLimit<V>>,
+
+    version_of: &'a Option<VersionExtractor<V>>,
+
+    replica: ReplicaId,
+
+    append_only: bool,
+
+    written: bool,
+
+    /// The register before the first write, kept to roll a rejected write back if the value is
+    /// checked when the guard is dropped
+    original: Option<LWWRegister<Entry<V>>>,
+}
+
+impl<K: Hash, V> Deref for ValueMut<'_, K, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.entry.get().value().get().expect("entry is live")
+    }
+}
+
+impl<K: Hash, V: Clone> DerefMut for ValueMut<'_, K, V> {
+    fn deref_mut(&mut self) -> &mut V {
+        if !self.written {
+            if self.append_only {
+                panic!("{}", WriteError::AlreadyExists);
+            }
+
+            if self.limit.is_some() || self.version_of.is_some() {
+                self.original = Some(self.entry.get().clone());
+            }
+
+            let Self { entry, digest, .. } = self;
+            digest.remove(entry.key(), entry.get());
+            let written = entry.get_mut().update_with(self.replica, |_| {});
+            if let Err(e) = written {
+                panic!("{e}");
+            }
+            digest.add(entry.key(), entry.get());
+            self.written = true;
+        }
+
+        match self.entry.get_mut().value_mut() {
+            Entry::Occupied(v) => v,
+            _ => unreachable!("entry is live"),
+        }
+    }
+}
+
+impl<K: Hash, V> Drop for ValueMut<'_, K, V> {
+    fn drop(&mut self) {
+        let Some(original) = self.original.take() else {
+            return;
+        };
+
+        let v = self.entry.get().value().get().expect("entry is live");
+        let rejected = if let Some(Err(e)) = self.limit.map(|limit| limit.check(v)) {
+            Some(WriteError::from(e))
+        } else if let (Some(version_of), Some(old)) = (self.version_of, original.value().get()) {
+            (version_of(v) < version_of(old)).then_some(WriteError::VersionRegression)
+        } else {
+            None
+        };
+        let Some(e) = rejected else {
+            return;
+        };
+
+        let Self { entry, digest, .. } = self;
+        digest.remove(entry.key(), entry.get());
+        *entry.get_mut() = original;
+        digest.add(entry.key(), entry.get());
+
+        if !std::thread::panicking() {
+            panic!("{e}");
+        }
+    }
+}
+
+impl<K, V> CRDT for LWWMap<K, V>
+where
+    K: Eq + Hash,
+{
+    type State = MapState<K, V>;
+
+    /// Incoming values that exceed [`Self::max_value_bytes`] are skipped.
+    /// A state from an older epoch is ignored, and a state from a newer epoch replaces the contents
+    /// of the map, see [`Self::reset`]
+    fn merge(&mut self, other: Self::State) {
+        self.merge_summary(other);
+    }
+
+    /// Pending coalesced writes are committed first, see [`Self::commit`]. Rejected writes are
+    /// dropped
+    fn take(mut self) -> Self::State {
+        self.commit();
+        self.state
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for LWWMap<K, V>
+where
+    K: Eq + Hash,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let iter = iter
+            .into_iter()
+            .map(|(k, v)| (k, LWWRegister::new(Entry::Occupied(v))));
+
+        Self::from(MapState::from_inner(iter.collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::lww::register::{LWWRegister, LWWState};
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::testing::XorShift;
+    use crate::crdt::CRDT;
+
+    use super::{
+        BucketHash, Conflict, Entry, LWWMap, MapState, MergeProgress, RenameError, WriteError,
+        WriteId,
+    };
+
+    #[test]
+    fn diff_classifies_changes() {
+        let mut old = LWWMap::new();
+        old.insert("updated", 0xBAD);
+        old.insert("removed", 0xDEAD);
+        old.insert("revived", 0xF00D);
+        old.remove("revived");
+        old.insert("untouched", 0xC0FFEE);
+
+        let mut new = LWWMap::new();
+        new.insert("added", 0xCAFE);
+        new.insert("updated", 0xBAD);
+        new.insert("updated", 0xC0DE);
+        new.insert("removed", 0xDEAD);
+        new.remove("removed");
+        new.insert("revived", 0xF00D);
+        new.remove("revived");
+        new.insert("revived", 0xBEEF);
+        new.insert("untouched", 0xC0FFEE);
+
+        let (old, new) = (old.take(), new.take());
+        let mut diff = old.diff(&new);
+        diff.added.sort();
+
+        assert_eq!(diff.added, vec![(&"added", &0xCAFE), (&"revived", &0xBEEF)]);
+        assert_eq!(diff.updated, vec![(&"updated", &0xBAD, &0xC0DE)]);
+        assert_eq!(diff.tombstoned, vec![(&"removed", &0xDEAD)]);
+    }
+
+    #[test]
+    fn diff_ignores_older_versions() {
+        let mut old = LWWMap::new();
+        old.insert("key", 0xBAD);
+        old.insert("key", 0xC0FFEE);
+        old.remove("key");
+
+        let mut new = LWWMap::new();
+        new.insert("key", 0xF00D);
+
+        let (old, new) = (old.take(), new.take());
+        let diff = old.diff(&new);
+
+        assert!(diff.added.is_empty());
+        assert!(diff.updated.is_empty());
+        assert!(diff.tombstoned.is_empty());
+    }
+
+    #[test]
+    fn state_round_trips_through_inner() {
+        let mut map = LWWMap::new();
+        map.insert("live", 0xC0FFEE);
+        map.insert("live", 0xF00D);
+        map.insert("removed", 0xBAD);
+        map.remove("removed");
+
+        let inner = map.clone().take().into_inner();
+        assert_eq!(inner["live"].seq(), 2);
+        assert_eq!(inner["removed"].seq(), 2);
+
+        let rebuilt = LWWMap::from(MapState::from_inner(inner));
+        assert_eq!(rebuilt, map);
+        assert_eq!(rebuilt.get("live"), Some(&0xF00D));
+        assert!(!rebuilt.contains_key("removed"));
+    }
+
+    #[test]
+    fn observer_fires_for_each_tombstone() {
+        use std::sync::{Arc, Mutex};
+
+        let tombstoned = Arc::new(Mutex::new(Vec::new()));
+
+        let mut map = LWWMap::new();
+        map.on_tombstone({
+            let tombstoned = Arc::clone(&tombstoned);
+            move |k: &&str| tombstoned.lock().unwrap().push(*k)
+        });
+
+        map.insert("local", 0xC0FFEE);
+        map.insert("remote", 0xBAD);
+        map.insert("kept", 0xF00D);
+
+        map.remove("local");
+        // Removing an absent or already removed key does not produce a tombstone
+        map.remove("local");
+        map.remove("absent");
+
+        let mut other = LWWMap::new();
+        other.insert("remote", 0xBAD);
+        other.remove("remote");
+        other.insert("kept", 0xF00D);
+        other.insert("gone", 0xDEAD);
+        other.remove("gone");
+        map.merge(other.take());
+
+        assert_eq!(*tombstoned.lock().unwrap(), vec!["local", "remote"]);
+        assert_eq!(map.get("kept"), Some(&0xF00D));
+    }
+
+    #[test]
+    fn gc_purges_stable_tombstones_only() {
+        use crate::crdt::stability::{StabilityTracker, UnknownReplica};
+
+        let (a, b) = (ReplicaId::new(1), ReplicaId::new(2));
+        let mut tracker = StabilityTracker::new([a, b]);
+
+        let mut map = LWWMap::new();
+        map.insert("live", 0xC0FFEE);
+        map.insert("removed", 0xBAD);
+        map.remove("removed");
+        map.insert("recent", 0xF00D);
+        map.insert("recent", 0xF00D);
+        map.remove("recent");
+
+        // Only one replica acknowledged the tombstones
+        tracker.ack_state(a, map.state()).unwrap();
+        assert_eq!(map.gc(|k, seq| tracker.is_stable(k, seq)), 0);
+
+        // A high seq acknowledged for another key says nothing about the tombstones
+        tracker.ack(b, "live", 10).unwrap();
+        assert_eq!(map.gc(|k, seq| tracker.is_stable(k, seq)), 0);
+
+        tracker.ack(b, "removed", 2).unwrap();
+        tracker.ack(b, "recent", 2).unwrap();
+        assert_eq!(map.gc(|k, seq| tracker.is_stable(k, seq)), 1);
+
+        // Replicas must be tracked before they acknowledge anything
+        let c = ReplicaId::new(3);
+        assert_eq!(tracker.ack(c, "recent", 3), Err(UnknownReplica(c)));
+        assert!(!tracker.is_stable("recent", 3));
+
+        let inner = map.take().into_inner();
+        assert!(inner.contains_key("live"));
+        assert!(inner.contains_key("recent"));
+        assert!(!inner.contains_key("removed"));
+    }
+
+    #[test]
+    fn merge_key_only_touches_that_key() {
+        let mut map = LWWMap::new();
+        map.insert("merged", 0xBAD);
+        map.insert("untouched", 0xC0FFEE);
+
+        let mut other = LWWMap::new();
+        other.insert("merged", 0xDEAD);
+        other.insert("merged", 0xF00D);
+        other.insert("untouched", 0xDEAD);
+        other.insert("untouched", 0xDEAD);
+        other.insert("fresh", 0xCAFE);
+        other.insert("fresh", 0xCAFE);
+
+        let mut other = other.take().into_inner();
+        let merged = other.remove("merged").unwrap();
+        let fresh = other.remove("fresh").unwrap();
+
+        assert!(map.merge_key("merged", CRDT::take(merged.clone())));
+        assert!(!map.merge_key("merged", CRDT::take(merged)));
+        assert!(map.merge_key("fresh", CRDT::take(fresh)));
+
+        assert_eq!(map.get("merged"), Some(&0xF00D));
+        assert_eq!(map.get("untouched"), Some(&0xC0FFEE));
+
+        // The seq of a key that was not known locally is preserved
+        let inner = map.take().into_inner();
+        assert_eq!(inner["fresh"].seq(), 2);
+        assert_eq!(inner["merged"].seq(), 2);
+    }
+
+    #[test]
+    fn tombstone_wins_ties() {
+        let build = || {
+            let mut live = LWWMap::new();
+            live.insert("key", 0xBAD);
+            live.insert("key", 0xC0FFEE);
+
+            let mut removed = LWWMap::new();
+            removed.insert("key", 0xBAD);
+            removed.remove("key");
+
+            (live, removed)
+        };
+
+        let (mut live, removed) = build();
+        live.merge(removed.take());
+        assert!(!live.contains_key("key"));
+
+        let (live, mut removed) = build();
+        removed.merge(live.take());
+        assert!(!removed.contains_key("key"));
+    }
+
+    #[test]
+    fn incremental_digest_matches_full_recomputation() {
+        let mut rng = XorShift::new(0xC0FFEE);
+        let mut next = move || rng.next_u64();
+
+        let mut a = LWWMap::new();
+        let mut b = LWWMap::new();
+
+        for round in 0..200 {
+            let map = if next() % 2 == 0 { &mut a } else { &mut b };
+            let k = next() % 16;
+
+            match next() % 3 {
+                0 => {
+                    map.remove(&k);
+                }
+                _ => {
+                    map.insert(k, round);
+                }
+            }
+
+            if round % 50 == 0 {
+                a.merge(b.clone().take());
+            }
+
+            assert_eq!(a.digest(), a.clone().take().digest());
+            assert_eq!(b.digest(), b.clone().take().digest());
+        }
+
+        a.gc(|_, _| true);
+        assert_eq!(a.digest(), a.clone().take().digest());
+    }
+
+    #[test]
+    fn digest_does_not_depend_on_order() {
+        let mut a = LWWMap::new();
+        a.insert("first", 0xC0FFEE);
+        a.insert("second", 0xBAD);
+
+        let mut b = LWWMap::new();
+        b.insert("second", 0xBAD);
+        b.insert("first", 0xC0FFEE);
+
+        assert_eq!(a.digest(), b.digest());
+
+        b.remove("first");
+        assert_ne!(a.digest(), b.digest());
+    }
+
+    #[test]
+    fn digest_is_pinned() {
+        // Digests are exchanged between replicas, they must not change with the toolchain
+        let mut map = LWWMap::new();
+        map.insert("key", 0xC0FFEE);
+        assert_eq!(map.digest(), 0xAD16_DDD0_7E47_4058);
+    }
+
+    #[test]
+    fn get_or_insert_with_only_computes_absent_values() {
+        let mut map = LWWMap::new();
+        map.insert("live", 0xC0FFEE);
+        map.insert("removed", 0xBAD);
+        map.remove("removed");
+
+        let mut calls = 0;
+        let mut compute = |v| {
+            calls += 1;
+            v
+        };
+
+        assert_eq!(
+            *map.get_or_insert_with("live", || compute(0xDEAD)),
+            0xC0FFEE
+        );
+        assert_eq!(
+            *map.get_or_insert_with("removed", || compute(0xF00D)),
+            0xF00D
+        );
+        assert_eq!(
+            *map.get_or_insert_with("absent", || compute(0xCAFE)),
+            0xCAFE
+        );
+        assert_eq!(
+            *map.get_or_insert_with("absent", || compute(0xDEAD)),
+            0xCAFE
+        );
+        assert_eq!(calls, 2);
+
+        assert_eq!(map.digest(), map.clone().take().digest());
+
+        let inner = map.take().into_inner();
+        assert_eq!(inner["live"].seq(), 1);
+        assert_eq!(inner["removed"].seq(), 3);
+        assert_eq!(inner["absent"].seq(), 1);
+    }
+
+    #[test]
+    fn remove_many_counts_live_keys() {
+        let mut map: LWWMap<_, _> = (0..10).map(|k| (k, k)).collect();
+        map.remove(&3);
+
+        assert_eq!(map.remove_many([1, 2, 3, 42, 2]), 2);
+
+        assert_eq!(map.iter().count(), 7);
+        assert!(!map.contains_key(&1));
+        assert!(!map.contains_key(&2));
+        assert_eq!(map.get(&4), Some(&4));
+        assert_eq!(map.digest(), map.clone().take().digest());
+    }
+
+    #[test]
+    fn apply_remote_update_winning() {
+        let mut map = LWWMap::new();
+        map.insert("key", 0xBAD);
+
+        assert!(map.apply_remote_update("key", Some(0xC0FFEE), 2, ReplicaId::new(1)));
+        assert_eq!(map.get("key"), Some(&0xC0FFEE));
+
+        // Same seq, but written by a replica with a higher id
+        assert!(map.apply_remote_update("key", Some(0xF00D), 2, ReplicaId::new(2)));
+        assert_eq!(map.get("key"), Some(&0xF00D));
+
+        assert!(map.apply_remote_update("fresh", Some(0xCAFE), 7, ReplicaId::new(1)));
+        assert_eq!(map.get("fresh"), Some(&0xCAFE));
+        assert_eq!(map.digest(), map.clone().take().digest());
+    }
+
+    #[test]
+    fn apply_remote_update_losing() {
+        let mut map = LWWMap::new();
+        map.insert("key", 0xBAD);
+        map.insert("key", 0xC0FFEE);
+        let digest = map.digest();
+
+        assert!(!map.apply_remote_update("key", Some(0xDEAD), 1, ReplicaId::new(1)));
+        assert!(!map.apply_remote_update("key", Some(0xDEAD), 2, ReplicaId::default()));
+        assert!(!map.apply_remote_update("key", None, 0, ReplicaId::new(1)));
+
+        assert_eq!(map.get("key"), Some(&0xC0FFEE));
+        assert_eq!(map.digest(), digest);
+    }
+
+    #[test]
+    fn apply_remote_update_tombstone() {
+        let mut map = LWWMap::new();
+        map.insert("key", 0xC0FFEE);
+
+        // A tombstone wins over a live value with the same seq, whatever the replica
+        assert!(map.apply_remote_update("key", None, 1, ReplicaId::default()));
+        assert!(!map.contains_key("key"));
+        assert!(!map.apply_remote_update("key", Some(0xF00D), 1, ReplicaId::new(2)));
+        assert!(!map.contains_key("key"));
+
+        assert!(map.apply_remote_update("absent", None, 3, ReplicaId::new(1)));
+        assert!(!map.contains_key("absent"));
+        assert_eq!(map.take().into_inner()["absent"].seq(), 3);
+    }
+
+    #[test]
+    fn oversized_local_writes_are_rejected() {
+        use super::{ValueTooLarge, WriteError};
+
+        let mut map = LWWMap::with_max_value_bytes(4, |v: &Vec<u8>| v.len());
+        assert_eq!(map.max_value_bytes(), Some(4));
+
+        map.insert("key", vec![0xC0; 4]);
+        let digest = map.digest();
+
+        assert_eq!(
+            map.try_insert("key", vec![0xBA; 5]),
+            Err(WriteError::TooLarge(ValueTooLarge { size: 5, max: 4 }))
+        );
+        assert_eq!(
+            map.try_insert("absent", vec![0xBA; 8]),
+            Err(WriteError::TooLarge(ValueTooLarge { size: 8, max: 4 }))
+        );
+
+        let too_large = WriteError::TooLarge(ValueTooLarge { size: 6, max: 4 });
+        assert_eq!(
+            map.try_get_or_insert_with("absent", || vec![0xBA; 6]),
+            Err(too_large)
+        );
+        assert_eq!(
+            map.try_insert_coalesced("key", vec![0xBA; 6]),
+            Err(too_large)
+        );
+        assert_eq!(map.commit().changed, 0);
+
+        assert_eq!(map.get("key"), Some(&vec![0xC0; 4]));
+        assert!(!map.contains_key("absent"));
+        assert_eq!(map.digest(), digest);
+        assert_eq!(map.take().into_inner()["key"].seq(), 1);
+    }
+
+    #[test]
+    fn merge_skips_oversized_entries() {
+        use std::sync::{Arc, Mutex};
+
+        let skipped = Arc::new(Mutex::new(Vec::new()));
+
+        let mut map = LWWMap::with_max_value_bytes(4, |v: &Vec<u8>| v.len());
+        map.on_oversized({
+            let skipped = Arc::clone(&skipped);
+            move |k: &&str, size| skipped.lock().unwrap().push((*k, size))
+        });
+        map.insert("key", vec![0xC0; 2]);
+
+        let mut other = LWWMap::new();
+        other.insert("key", vec![0xBA; 2]);
+        other.insert("key", vec![0xBA; 6]);
+        other.insert("fits", vec![0xF0; 4]);
+        other.insert("large", vec![0xDE; 16]);
+        other.insert("removed", vec![0xDE; 16]);
+        other.remove("removed");
+
+        map.merge(other.take());
+
+        assert_eq!(map.get("key"), Some(&vec![0xC0; 2]));
+        assert_eq!(map.get("fits"), Some(&vec![0xF0; 4]));
+        assert!(!map.contains_key("large"));
+
+        let mut skipped = skipped.lock().unwrap().clone();
+        skipped.sort();
+        assert_eq!(skipped, vec![("key", 6), ("large", 16)]);
+    }
+
+    #[test]
+    fn iter_sorted_does_not_depend_on_insert_order() {
+        let mut a = LWWMap::new();
+        let mut b = LWWMap::new();
+
+        for k in 0..32 {
+            a.insert(k, k * 2);
+            b.insert(31 - k, (31 - k) * 2);
+        }
+        a.remove(&7);
+        b.remove(&7);
+
+        assert!(a.iter_sorted().eq(b.iter_sorted()));
+        assert_eq!(
+            a.iter_sorted().take(8).map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4, 5, 6, 8]
+        );
+    }
+
+    #[test]
+    fn stats_summarize_entries() {
+        let mut map = LWWMap::new();
+        map.insert("live", 0xC0FFEE);
+        map.insert("live", 0xF00D);
+        map.insert("removed", 0xBAD);
+        map.remove("removed");
+        map.insert("other", 0xCAFE);
+        map.apply_remote_update("remote", Some(0xDEAD), 5, ReplicaId::new(1));
+        map.apply_remote_update("gone", None, 3, ReplicaId::new(1));
+
+        let stats = map.stats();
+        assert_eq!(stats.live, 3);
+        assert_eq!(stats.tombstones, 2);
+        assert_eq!(stats.max_seq, 5);
+        assert_eq!(stats.writes[&ReplicaId::default()], 3);
+        assert_eq!(stats.writes[&ReplicaId::new(1)], 2);
+
+        map.gc(|_, _| true);
+        assert_eq!(map.stats().tombstones, 0);
+    }
+
+    #[test]
+    fn merge_entries_matches_state_merge() {
+        use super::Entry;
+
+        let mut base = LWWMap::with_replica(ReplicaId::new(1));
+        base.insert("updated", 0xBAD);
+        base.insert("removed", 0xDEAD);
+        base.insert("stale", 0xC0FFEE);
+        base.insert("stale", 0xC0FFEE);
+        base.insert("concurrent", 0xC0FFEE);
+
+        // Written concurrently with the same seq, the version of the highest replica wins
+        let mut other = LWWMap::with_replica(ReplicaId::new(2));
+        other.insert("concurrent", 0xF00D);
+        other.insert("updated", 0xBAD);
+        other.insert("updated", 0xF00D);
+        other.insert("removed", 0xDEAD);
+        other.remove("removed");
+        other.insert("stale", 0xDEAD);
+        other.insert("fresh", 0xCAFE);
+
+        let entries: Vec<_> = other
+            .clone()
+            .take()
+            .into_inner()
+            .into_iter()
+            .map(|(k, reg)| {
+                let (entry, seq, replica) = CRDT::take(reg).into_parts();
+                (k, entry, seq, replica)
+            })
+            .collect();
+
+        let mut streamed = base.clone();
+        assert!(streamed.merge_entries(entries.clone()));
+        assert!(!streamed.merge_entries(entries));
+        assert!(!streamed.merge_entries([("zero", Entry::Occupied(0xBAD), 0, ReplicaId::new(2))]));
+
+        base.merge(other.take());
+        assert_eq!(streamed, base);
+        assert_eq!(streamed.digest(), base.digest());
+        assert_eq!(streamed.get("concurrent"), Some(&0xF00D));
+        assert_eq!(streamed.stats().tombstones, 1);
+    }
+
+    #[test]
+    fn merge_captures_concurrent_losers() {
+        let build = || {
+            let mut a = LWWMap::new();
+            a.apply_remote_update("concurrent", Some(0xC0FFEE), 2, ReplicaId::new(1));
+            a.apply_remote_update("same", Some(0xF00D), 1, ReplicaId::new(1));
+            a.apply_remote_update("newer", Some(0xBAD), 1, ReplicaId::new(1));
+
+            let mut b = LWWMap::new();
+            b.apply_remote_update("concurrent", Some(0xCAFE), 2, ReplicaId::new(2));
+            b.apply_remote_update("same", Some(0xF00D), 1, ReplicaId::new(1));
+            b.apply_remote_update("newer", Some(0xDEAD), 3, ReplicaId::new(1));
+
+            (a, b)
+        };
+
+        let winner = WriteId {
+            replica: ReplicaId::new(2),
+            seq: 2,
+        };
+        let loser = WriteId {
+            replica: ReplicaId::new(1),
+            seq: 2,
+        };
+        let conflict = Conflict {
+            key: "concurrent",
+            value: 0xC0FFEE,
+            winner,
+            loser,
+        };
+
+        let (mut a, b) = build();
+        let losers = a.merge_capturing_losers(b.take());
+        assert_eq!(losers, vec![conflict.clone()]);
+        assert_eq!(a.get("concurrent"), Some(&0xCAFE));
+        assert_eq!(a.get("newer"), Some(&0xDEAD));
+
+        // The loser is the same whichever side the merge is performed from
+        let (a, mut b) = build();
+        let losers = b.merge_capturing_losers(a.take());
+        assert_eq!(losers, vec![conflict]);
+        assert_eq!(b.get("concurrent"), Some(&0xCAFE));
+    }
+
+    #[test]
+    fn captured_losers_follow_key_policies() {
+        use super::MergePolicy;
+
+        let build = |ours: Option<i32>| {
+            let mut a = LWWMap::with_replica(ReplicaId::new(2));
+            a.apply_remote_update("key", ours, 1, ReplicaId::new(2));
+            a.set_key_policy("key", MergePolicy::MaxValue);
+
+            let mut b = LWWMap::with_replica(ReplicaId::new(1));
+            b.apply_remote_update("key", Some(100), 1, ReplicaId::new(1));
+
+            (a, b.take())
+        };
+
+        // The local version wins the tie-break, but the policy keeps the incoming one
+        let (mut merged, other) = build(Some(1));
+        merged.merge(other.clone());
+        let (mut captured, other) = build(Some(1));
+        let losers = captured.merge_capturing_losers(other);
+        assert_eq!(captured, merged);
+        assert_eq!(captured.get("key"), Some(&100));
+        assert_eq!(
+            losers,
+            vec![Conflict {
+                key: "key",
+                value: 1,
+                winner: WriteId {
+                    replica: ReplicaId::new(1),
+                    seq: 1,
+                },
+                loser: WriteId {
+                    replica: ReplicaId::new(2),
+                    seq: 1,
+                },
+            }]
+        );
+
+        // With a policy, a live value wins over a concurrent tombstone
+        let (mut merged, other) = build(None);
+        merged.merge(other.clone());
+        let (mut captured, other) = build(None);
+        assert_eq!(captured.merge_capturing_losers(other), vec![]);
+        assert_eq!(captured, merged);
+        assert_eq!(captured.get("key"), Some(&100));
+    }
+
+    #[test]
+    fn coalesced_writes_bump_seq_once() {
+        let mut map = LWWMap::new();
+        map.insert("key", 0);
+        map.insert("removed", 0xC0FFEE);
+
+        for i in 1..=100 {
+            map.insert_coalesced("key", i);
+        }
+        map.insert_coalesced("removed", 0xBAD);
+        map.remove_coalesced("removed");
+        map.remove_coalesced("absent");
+
+        // Uncommitted writes are not visible yet
+        assert_eq!(map.get("key"), Some(&0));
+        assert_eq!(map.seq_of("key"), Some(1));
+
+        // Removing an absent key does not change anything
+        assert_eq!(map.commit().changed, 2);
+        assert_eq!(map.commit().changed, 0);
+        assert_eq!(map.seq_of("absent"), None);
+
+        assert_eq!(map.get("key"), Some(&100));
+        assert!(!map.contains_key("removed"));
+
+        let inner = map.clone().take().into_inner();
+        assert_eq!(inner["key"].seq(), 2);
+        assert_eq!(inner["removed"].seq(), 2);
+
+        // Taking the state does not lose pending writes
+        map.insert_coalesced("key", 0xF00D);
+        let inner = map.take().into_inner();
+        assert_eq!(inner["key"].value().get(), Some(&0xF00D));
+        assert_eq!(inner["key"].seq(), 3);
+    }
+
+    #[test]
+    fn rejected_coalesced_writes_are_reported() {
+        use super::Committed;
+
+        let mut map = LWWMap::append_only();
+        map.insert("existing", 0xC0FFEE);
+        assert_eq!(
+            map.try_insert_coalesced("existing", 0xBAD),
+            Err(WriteError::AlreadyExists)
+        );
+
+        // The key becomes live after the write has been buffered
+        map.insert_coalesced("merged", 0xBAD);
+        map.insert_coalesced("fresh", 0xF00D);
+        let mut other = LWWMap::new();
+        other.insert("merged", 0xCAFE);
+        map.merge(other.take());
+
+        assert_eq!(
+            map.commit(),
+            Committed {
+                changed: 1,
+                rejected: vec![("merged", WriteError::AlreadyExists)],
+            }
+        );
+        assert_eq!(map.get("merged"), Some(&0xCAFE));
+        assert_eq!(map.get("fresh"), Some(&0xF00D));
+        assert_eq!(map.get("existing"), Some(&0xC0FFEE));
+
+        let mut map = LWWMap::new();
+        map.set_version_extractor(|v: &u64| *v);
+        map.insert("key", 2);
+        assert_eq!(
+            map.try_insert_coalesced("key", 1),
+            Err(WriteError::VersionRegression)
+        );
+        map.insert_coalesced("key", 3);
+        map.insert("key", 4);
+        assert_eq!(
+            map.commit().rejected,
+            vec![("key", WriteError::VersionRegression)]
+        );
+        assert_eq!(map.get("key"), Some(&4));
+    }
+
+    #[test]
+    fn remove_prefix_removes_matching_keys() {
+        let fields = ["name", "email", "avatar"];
+        let mut a: LWWMap<_, _> = [1, 2]
+            .into_iter()
+            .flat_map(|user| fields.map(|field| ((user, field), 0xC0FFEE)))
+            .collect();
+        let mut b = a.clone();
+
+        a.remove(&(1, "avatar"));
+        assert_eq!(a.remove_prefix(|(user, _)| *user == 1), 2);
+        assert_eq!(a.remove_prefix(|(user, _)| *user == 1), 0);
+
+        b.insert((2, "email"), 0xF00D);
+        assert_converges!(a, b);
+
+        a.merge(b.take());
+        assert_eq!(a.iter().filter(|((user, _), _)| *user == 1).count(), 0);
+        assert_eq!(a.iter().filter(|((user, _), _)| *user == 2).count(), 3);
+        assert_eq!(a.get(&(2, "email")), Some(&0xF00D));
+        assert_eq!(a.digest(), a.clone().take().digest());
+    }
+
+    #[test]
+    fn merge_reports_changes_and_conflicts() {
+        use std::sync::{Arc, Mutex};
+
+        use super::MergeReport;
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+
+        let mut map = LWWMap::new();
+        map.on_merge({
+            let reports = Arc::clone(&reports);
+            move |report: &MergeReport| reports.lock().unwrap().push(*report)
+        });
+        map.insert("updated", 0xBAD);
+        map.insert("conflict", 0xBAD);
+        map.insert("untouched", 0xC0FFEE);
+
+        let mut other = LWWMap::new();
+        other.insert("updated", 0xBAD);
+        other.insert("updated", 0xF00D);
+        other.apply_remote_update("conflict", Some(0xDEAD), 1, ReplicaId::new(1));
+        other.insert("untouched", 0xC0FFEE);
+        other.insert("fresh", 0xCAFE);
+
+        map.merge(other.take());
+
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![MergeReport {
+                changed: 3,
+                unchanged: 1,
+                conflicts: 1,
+                skipped: 0,
+            }]
+        );
+        assert_eq!(map.get("conflict"), Some(&0xDEAD));
+    }
+
+    #[test]
+    fn bucket_digests_narrow_down_diverging_key() {
+        let a: LWWMap<_, _> = (0..16).map(|k| (k, k)).collect();
+        let mut b = a.clone();
+
+        assert!(a
+            .diverging_keys(10, |buckets, which| {
+                let digests = b.key_digests(buckets);
+                which.iter().map(|&i| digests[i]).collect()
+            })
+            .is_empty());
+
+        b.insert(7, 0xC0FFEE);
+
+        let mut asked = 0;
+        let diverging = a.diverging_keys(10, |buckets, which| {
+            asked += which.len();
+            let digests = b.key_digests(buckets);
+            which.iter().map(|&i| digests[i]).collect()
+        });
+
+        assert_eq!(diverging, vec![&7]);
+        // Only the buckets that hold the diverging key are split
+        assert_eq!(asked, 1 + 2 * 10);
+    }
+
+    #[test]
+    fn buckets_are_pinned() {
+        // Bucket digests are exchanged between replicas, keys must fall into the same bucket
+        // whatever the toolchain
+        let mut map = LWWMap::new();
+        map.insert("key", 0xC0FFEE);
+
+        let digests = map.key_digests(16);
+        assert_ne!(digests[9], BucketHash::default());
+        assert_eq!(
+            digests
+                .iter()
+                .filter(|&&d| d != BucketHash::default())
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn reset_wins_over_concurrent_edits() {
+        let mut a: LWWMap<_, _> = [("kept", 0xBAD), ("dropped", 0xDEAD)].into_iter().collect();
+        let mut b = a.clone();
+
+        // Concurrent edits, with many more writes than the reset
+        for i in 0..10 {
+            b.insert("kept", i);
+            b.insert("added", i);
+        }
+        a.reset([("kept", 0xC0FFEE), ("config", 0xF00D)]);
+        assert_eq!(a.epoch(), 1);
+
+        let (sa, sb) = (a.clone().take(), b.clone().take());
+        a.merge(sb);
+        b.merge(sa);
+
+        for map in [&a, &b] {
+            assert_eq!(
+                map.iter_sorted().collect::<Vec<_>>(),
+                vec![(&"config", &0xF00D), (&"kept", &0xC0FFEE)]
+            );
+            assert_eq!(map.digest(), map.clone().take().digest());
+        }
+        assert_eq!(a, b);
+        assert_eq!(a.digest(), b.digest());
+
+        // Edits made after the reset merge key by key again
+        b.insert("added", 0xCAFE);
+        a.merge(b.take());
+        assert_eq!(a.get("added"), Some(&0xCAFE));
+    }
+
+    #[test]
+    fn new_epoch_notifies_dropped_keys() {
+        use std::sync::{Arc, Mutex};
+
+        let tombstoned = Arc::new(Mutex::new(Vec::new()));
+
+        let mut a: LWWMap<_, _> = [("kept", 0xBAD), ("dropped", 0xDEAD), ("removed", 0xF00D)]
+            .into_iter()
+            .collect();
+        a.remove("removed");
+        let mut b = a.clone();
+        b.on_tombstone({
+            let tombstoned = Arc::clone(&tombstoned);
+            move |k: &&str| tombstoned.lock().unwrap().push(*k)
+        });
+
+        b.insert("local", 0xCAFE);
+        a.reset([("kept", 0xC0FFEE)]);
+        b.merge(a.clone().take());
+
+        let mut keys = tombstoned.lock().unwrap().clone();
+        keys.sort();
+        assert_eq!(keys, vec!["dropped", "local"]);
+
+        tombstoned.lock().unwrap().clear();
+        b.reset([("kept", 0xC0FFEE), ("config", 0xF00D)]);
+        assert!(tombstoned.lock().unwrap().is_empty());
+        b.reset([("config", 0xF00D)]);
+        assert_eq!(*tombstoned.lock().unwrap(), vec!["kept"]);
+    }
+
+    #[test]
+    fn conflicts_are_not_reported_across_epochs() {
+        let mut a = LWWMap::with_replica(ReplicaId::new(1));
+        a.insert("key", 0xC0FFEE);
+        let mut b = a.fork(ReplicaId::new(2));
+        b.insert("key", 0xBAD);
+        a.insert("key", 0xF00D);
+        assert_eq!(a.concurrent_keys(b.state()), vec!["key"]);
+
+        // The reset writes the key with the same sequence number, but wins wholesale
+        b.reset([("key", 0xDEAD)]);
+        b.insert("key", 0xCAFE);
+        assert!(a.concurrent_keys(b.state()).is_empty());
+        assert!(b.concurrent_keys(a.state()).is_empty());
+
+        let stale = a.clone().take();
+        assert!(a.merge_capturing_losers(b.clone().take()).is_empty());
+        assert_eq!(a.get("key"), Some(&0xCAFE));
+        assert!(b.merge_capturing_losers(stale).is_empty());
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn merge_summary_counts_each_category() {
+        use super::MergeSummary;
+
+        let mut map = LWWMap::new();
+        map.insert("updated", 0xBAD);
+        map.insert("removed", 0xDEAD);
+        map.insert("revived", 0xF00D);
+        map.remove("revived");
+        map.insert("conflict", 0xBAD);
+        map.insert("stale", 0xC0FFEE);
+        map.insert("stale", 0xC0FFEE);
+
+        let mut other = LWWMap::new();
+        other.insert("updated", 0xBAD);
+        other.insert("updated", 0xC0DE);
+        other.insert("removed", 0xDEAD);
+        other.remove("removed");
+        other.apply_remote_update("revived", Some(0xBEEF), 3, ReplicaId::default());
+        other.apply_remote_update("conflict", Some(0xCAFE), 1, ReplicaId::new(1));
+        other.insert("stale", 0xDEAD);
+        other.insert("fresh", 0xCAFE);
+
+        assert_eq!(
+            map.merge_summary(other.take()),
+            MergeSummary {
+                inserted: 2,
+                updated: 2,
+                tombstoned: 1,
+                conflicts_resolved: 1,
+                ignored_stale: 1,
+            }
+        );
+        assert_eq!(map.get("conflict"), Some(&0xCAFE));
+        assert_eq!(map.get("revived"), Some(&0xBEEF));
+    }
+
+    #[test]
+    fn soft_remove_then_restore() {
+        let mut a = LWWMap::new();
+        a.insert("key", 0xC0FFEE);
+        let mut b = a.clone();
+
+        assert!(a.soft_remove("key"));
+        assert!(!a.soft_remove("key"));
+        assert_eq!(a.get("key"), None);
+        assert_eq!(a.get_trashed("key"), Some(&0xC0FFEE));
+
+        b.merge(a.clone().take());
+        assert!(!b.contains_key("key"));
+        assert!(b.restore("key"));
+        assert!(!b.restore("key"));
+
+        a.merge(b.take());
+        assert_eq!(a.get("key"), Some(&0xC0FFEE));
+        assert_eq!(a.get_trashed("key"), None);
+        assert_eq!(a.digest(), a.clone().take().digest());
+    }
+
+    #[test]
+    fn hard_remove_beats_soft_remove() {
+        let mut a = LWWMap::new();
+        a.insert("trashed", 0xC0FFEE);
+        a.insert("concurrent", 0xBAD);
+        let (mut b, mut c) = (a.clone(), a.clone());
+
+        a.soft_remove("trashed");
+        assert_eq!(a.hard_remove("trashed"), Some(0xC0FFEE));
+        assert_eq!(a.hard_remove("trashed"), None);
+        b.merge(a.clone().take());
+        assert_eq!(b.get_trashed("trashed"), None);
+        assert!(!b.restore("trashed"));
+
+        // A hard removal beats a concurrent soft removal, which beats a concurrent write
+        a.soft_remove("concurrent");
+        b.hard_remove("concurrent");
+        c.insert("concurrent", 0xF00D);
+
+        assert_converges!(a, b);
+        assert_converges!(a, c);
+        assert_converges!(b, c);
+
+        a.merge(c.take());
+        assert_eq!(a.get_trashed("concurrent"), Some(&0xBAD));
+        a.merge(b.take());
+        assert_eq!(a.get_trashed("concurrent"), None);
+        assert_eq!(a.stats().tombstones, 2);
+    }
+
+    /// A merge of a whole state through one of the public merge entry points of [`LWWMap`]
+    type Merge = fn(&mut LWWMap<&'static str, i32>, MapState<&'static str, i32>);
+
+    /// Every public entry point that merges a state, so that a test can check that they agree
+    const MERGES: [(&str, Merge); 8] = [
+        ("merge", |map, state| map.merge(state)),
+        ("merge_ref", |map, state| map.merge_ref(&state)),
+        ("merge_summary", |map, state| {
+            map.merge_summary(state);
+        }),
+        ("merge_capturing_losers", |map, state| {
+            map.merge_capturing_losers(state);
+        }),
+        ("merge_converted", |map, state| {
+            map.merge_converted(state, |v| v)
+        }),
+        ("merge_chunked", |map, mut state| {
+            while let MergeProgress::Pending(rest) = map.merge_chunked(state, 1) {
+                state = rest;
+            }
+        }),
+        ("merge_entries", |map, state| {
+            map.merge_entries(state.into_inner().into_iter().map(|(k, reg)| {
+                let (entry, seq, replica) = CRDT::take(reg).into_parts();
+                (k, entry, seq, replica)
+            }));
+        }),
+        ("merge_key", |map, state| {
+            for (k, reg) in state.into_inner() {
+                map.merge_key(k, CRDT::take(reg));
+            }
+        }),
+    ];
+
+    #[test]
+    fn key_policy_overrides_last_write_wins() {
+        use super::MergePolicy;
+
+        let mut a = LWWMap::new();
+        a.insert("schema", 3);
+        a.insert("first", 0xC0FFEE);
+        a.insert("plain", 3);
+        let mut b = a.clone();
+
+        a.insert("schema", 7);
+        a.insert("plain", 7);
+        for _ in 0..3 {
+            b.insert("schema", 5);
+            b.insert("plain", 5);
+        }
+        b.insert("first", 0xBAD);
+
+        for map in [&mut a, &mut b] {
+            map.set_key_policy("schema", MergePolicy::MaxValue);
+            map.set_key_policy("first", MergePolicy::NeverOverwrite);
+        }
+        assert_eq!(a.key_policy("schema"), MergePolicy::MaxValue);
+        assert_eq!(a.key_policy("plain"), MergePolicy::LastWriteWins);
+
+        assert_converges!(a, b);
+
+        for (name, merge) in MERGES {
+            let mut b = b.clone();
+            merge(&mut b, a.state().clone());
+
+            // The smaller value has the higher seq but the larger one is kept
+            assert_eq!(b.get("schema"), Some(&7), "{name}");
+            assert_eq!(b.get("first"), Some(&0xC0FFEE), "{name}");
+            assert_eq!(b.get("plain"), Some(&5), "{name}");
+        }
+    }
+
+    #[test]
+    fn key_policies_converge_in_any_merge_order() {
+        use super::MergePolicy;
+
+        let replica = |value: Option<i32>, seq, id| {
+            let entry = value.map_or(Entry::Tombstoned, Entry::Occupied);
+            let state = LWWState::from_parts(Some(entry), seq, ReplicaId::new(id)).unwrap();
+            MapState::from_inner(
+                [("key", LWWRegister::from_state(state))]
+                    .into_iter()
+                    .collect(),
+            )
+        };
+        let states = [
+            replica(Some(7), 2, 1),
+            replica(Some(5), 4, 2),
+            replica(None, 3, 3),
+        ];
+        let orders = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+
+        for (policy, expected) in [
+            (MergePolicy::MaxValue, 7),
+            (MergePolicy::MinValue, 5),
+            (MergePolicy::NeverOverwrite, 7),
+        ] {
+            let with_policy = |state: &MapState<_, _>| {
+                let mut map = LWWMap::from(state.clone());
+                map.set_key_policy("key", policy);
+                map
+            };
+
+            for ([x, y, z], (name, merge)) in orders
+                .into_iter()
+                .flat_map(|order| MERGES.map(|merge| (order, merge)))
+            {
+                // (x <- y) <- z
+                let mut sequential = with_policy(&states[x]);
+                merge(&mut sequential, states[y].clone());
+                merge(&mut sequential, states[z].clone());
+
+                // x <- (y <- z)
+                let mut grouped = with_policy(&states[y]);
+                merge(&mut grouped, states[z].clone());
+                let mut nested = with_policy(&states[x]);
+                merge(&mut nested, grouped.take());
+
+                for map in [&sequential, &nested] {
+                    assert_eq!(
+                        map.get("key"),
+                        Some(&expected),
+                        "{policy:?} {name} {:?}",
+                        [x, y, z]
+                    );
+                }
+                assert_eq!(sequential, nested, "{policy:?} {name} {:?}", [x, y, z]);
+            }
+        }
+    }
+
+    #[test]
+    fn keys_with_a_policy_can_not_be_removed() {
+        use super::MergePolicy;
+
+        let mut map = LWWMap::new();
+        map.insert("schema", 3);
+        map.insert("plain", 3);
+        map.set_key_policy("schema", MergePolicy::MaxValue);
+
+        assert_eq!(map.try_remove("schema"), Err(WriteError::HasPolicy));
+        assert!(!map.soft_remove("schema"));
+        assert_eq!(map.hard_remove("schema"), None);
+        assert_eq!(map.remove_many(["schema", "plain"]), 1);
+        assert_eq!(map.rename("schema", "renamed"), Err(RenameError::HasPolicy));
+        assert_eq!(map.get("schema"), Some(&3));
+
+        // Without a policy, the key can be removed again
+        map.set_key_policy("schema", MergePolicy::LastWriteWins);
+        assert_eq!(map.remove("schema"), Some(3));
+    }
+
+    #[test]
+    fn merge_direction_does_not_matter() {
+        #[derive(Debug, Clone, Copy)]
+        enum Side {
+            Absent,
+            Live(u64),
+            Tombstoned(u64),
+        }
+
+        let build = |side, replica| {
+            let mut map = LWWMap::new();
+            map.insert("other", 0xF00D);
+
+            let replica = ReplicaId::new(replica);
+            match side {
+                Side::Absent => {}
+                Side::Live(seq) => {
+                    map.apply_remote_update("key", Some(replica.get()), seq, replica);
+                }
+                Side::Tombstoned(seq) => {
+                    map.apply_remote_update("key", None, seq, replica);
+                }
+            }
+            map
+        };
+
+        let sides = |seq| [Side::Absent, Side::Live(seq), Side::Tombstoned(seq)];
+        for (seq_a, seq_b) in [(1, 1), (1, 2), (2, 1)] {
+            for a in sides(seq_a) {
+                for b in sides(seq_b) {
+                    // Concurrent writes by different replicas, and the same write seen by both
+                    assert_converges!(build(a, 1), build(b, 2));
+                    assert_converges!(build(a, 1), build(b, 1));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn append_only_rejects_local_mutations() {
+        use super::WriteError;
+
+        let mut map = LWWMap::append_only();
+        assert!(map.is_append_only());
+
+        assert_eq!(map.try_insert("key", 0xC0FFEE), Ok(None));
+        assert_eq!(map.try_insert("key", 0xBAD), Err(WriteError::AlreadyExists));
+        assert_eq!(map.try_remove("key"), Err(WriteError::Immutable));
+        assert_eq!(map.try_remove("absent"), Err(WriteError::Immutable));
+
+        assert_eq!(map.get("key"), Some(&0xC0FFEE));
+        assert_eq!(map.take().into_inner()["key"].seq(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "append-only")]
+    fn append_only_panics_on_remove() {
+        let mut map = LWWMap::append_only();
+        map.insert("key", 0xC0FFEE);
+        map.remove_many(["key"]);
+    }
+
+    #[test]
+    fn append_only_merges_converge() {
+        let mut a = LWWMap::append_only();
+        let mut b = LWWMap::append_only();
+
+        a.apply_remote_update("key", Some(0xC0FFEE), 1, ReplicaId::new(1));
+        b.apply_remote_update("key", Some(0xBAD), 1, ReplicaId::new(2));
+        a.insert("a", 0xA);
+        b.insert("b", 0xB);
+
+        assert_converges!(a, b);
+
+        a.merge(b.take());
+        assert_eq!(a.get("key"), Some(&0xBAD));
+        assert_eq!(a.get("b"), Some(&0xB));
+    }
+
+    #[test]
+    fn window_pages_through_sorted_entries() {
+        let mut map: LWWMap<_, _> = (0..10).rev().map(|k| (k, k * 10)).collect();
+        map.remove(&3);
+
+        assert_eq!(map.window(0, 3), vec![(&0, &0), (&1, &10), (&2, &20)]);
+        assert_eq!(map.window(3, 2), vec![(&4, &40), (&5, &50)]);
+        assert_eq!(map.window(7, 5), vec![(&8, &80), (&9, &90)]);
+        assert!(map.window(9, 5).is_empty());
+        assert!(map.window(0, 0).is_empty());
+    }
+
+    #[test]
+    fn chunked_merge_equals_single_merge() {
+        use super::MergeProgress;
+
+        let mut base: LWWMap<_, _> = (0..20).map(|k| (k, k)).collect();
+        let mut other = base.clone();
+        for k in (0..40).step_by(3) {
+            other.insert(k, k * 100);
+        }
+        other.remove(&4);
+        base.insert(5, 0xBAD);
+
+        let mut chunked = base.clone();
+        let mut state = other.clone().take();
+        let mut calls = 0;
+        while let MergeProgress::Pending(rest) = chunked.merge_chunked(state, 4) {
+            state = rest;
+            calls += 1;
+        }
+
+        base.merge(other.take());
+        assert_eq!(chunked, base);
+        assert_eq!(chunked.digest(), base.digest());
+        // 27 keys merged 4 at a time
+        assert_eq!(calls, 6);
+    }
+
+    #[test]
+    fn iter_filters_by_variant() {
+        #[derive(Debug, PartialEq)]
+        enum Shape {
+            Circle(u32),
+            Square(u32),
+        }
+
+        let mut map: LWWMap<_, _> = [
+            ("small", Shape::Circle(1)),
+            ("big", Shape::Circle(0xC0FFEE)),
+            ("box", Shape::Square(2)),
+            ("gone", Shape::Circle(3)),
+        ]
+        .into_iter()
+        .collect();
+        map.remove("gone");
+
+        let mut circles: Vec<_> = map
+            .iter_variant(|v| match v {
+                Shape::Circle(r) => Some(r),
+                _ => None,
+            })
+            .collect();
+        circles.sort();
+        assert_eq!(circles, vec![(&"big", &0xC0FFEE), (&"small", &1)]);
+
+        let squares: Vec<_> = map
+            .iter_matching(|v| matches!(v, Shape::Square(_)))
+            .collect();
+        assert_eq!(squares, vec![(&"box", &Shape::Square(2))]);
+    }
+
+    #[test]
+    fn rename_converges() {
+        let mut a = LWWMap::new();
+        a.insert("old", 0xC0FFEE);
+        a.insert("new", 0xBAD);
+        a.insert("new", 0xBAD);
+
+        let mut b = a.clone();
+        assert_eq!(a.rename("old", "new"), Ok(()));
+        assert_eq!(a.get("new"), Some(&0xC0FFEE));
+        assert!(!a.contains_key("old"));
+
+        assert_converges!(a.clone(), b.clone());
+        b.merge(a.clone().take());
+        assert_eq!(b.get("new"), Some(&0xC0FFEE));
+        assert!(!b.contains_key("old"));
+
+        // Concurrent renames of the same key to different keys keep the value under both keys
+        let mut c = b.clone();
+        b.rename("new", "left").unwrap();
+        c.rename("new", "right").unwrap();
+        assert_converges!(b.clone(), c.clone());
+        b.merge(c.take());
+        assert_eq!(b.get("left"), Some(&0xC0FFEE));
+        assert_eq!(b.get("right"), Some(&0xC0FFEE));
+        assert!(!b.contains_key("new"));
+
+        assert_eq!(b.rename("new", "other"), Err(RenameError::NotFound));
+        let mut frozen = LWWMap::append_only();
+        frozen.insert("old", 0xF00D);
+        assert_eq!(frozen.rename("old", "new"), Err(RenameError::Immutable));
+    }
+
+    #[test]
+    fn rename_races_with_writes_to_from() {
+        let mut a = LWWMap::with_replica(ReplicaId::new(1));
+        a.insert("old", 0xC0FFEE);
+        let mut b = a.fork(ReplicaId::new(2));
+
+        // A concurrent write with a higher seq wins over the tombstone
+        a.rename("old", "new").unwrap();
+        for _ in 0..3 {
+            b.insert("old", 0xBAD);
+        }
+        assert_converges!(a, b);
+        b.merge(a.clone().take());
+        assert_eq!(b.get("old"), Some(&0xBAD));
+        assert_eq!(b.get("new"), Some(&0xC0FFEE));
+
+        // A concurrent write with a lower seq loses to the tombstone
+        let mut a = LWWMap::with_replica(ReplicaId::new(1));
+        a.insert("old", 0xC0FFEE);
+        let mut b = a.fork(ReplicaId::new(2));
+        for _ in 0..3 {
+            a.insert("new", 0xF00D);
+        }
+        a.rename("old", "new").unwrap();
+        b.insert("old", 0xBAD);
+        assert_converges!(a, b);
+        b.merge(a.take());
+        assert!(!b.contains_key("old"));
+        assert_eq!(b.get("new"), Some(&0xC0FFEE));
+    }
+
+    #[test]
+    fn rename_checks_version_fence_of_destination() {
+        let mut map = LWWMap::new();
+        map.set_version_extractor(|v: &u64| *v);
+        map.insert("old", 1);
+        map.insert("new", 2);
+
+        assert_eq!(
+            map.rename("old", "new"),
+            Err(RenameError::VersionRegression)
+        );
+        assert_eq!(map.get("old"), Some(&1));
+        assert_eq!(map.get("new"), Some(&2));
+
+        map.insert("old", 3);
+        assert_eq!(map.rename("old", "new"), Ok(()));
+        assert_eq!(map.get("new"), Some(&3));
+    }
+
+    #[test]
+    fn auto_gc_purges_safe_tombstones_past_ratio() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::crdt::stability::StabilityTracker;
+
+        let mut map: LWWMap<_, _> = [("a", 1), ("b", 2), ("c", 3), ("d", 4)]
+            .into_iter()
+            .collect();
+        for i in 0..5 {
+            map.insert("hot", i);
+        }
+
+        // Both replicas observed the removal of "a", only this one observed the removal of "hot"
+        let (local, remote) = (ReplicaId::new(1), ReplicaId::new(2));
+        let tracker = Arc::new(Mutex::new(StabilityTracker::new([local, remote])));
+        tracker.lock().unwrap().ack(remote, "a", 2).unwrap();
+        map.auto_gc(0.5, {
+            let tracker = Arc::clone(&tracker);
+            move |k, seq| tracker.lock().unwrap().is_stable(k, seq)
+        });
+
+        map.remove("a");
+        assert_eq!(map.stats().tombstones, 1);
+
+        // 2 tombstones for 3 live keys crosses the ratio, only the tombstone of "a" is safe
+        tracker
+            .lock()
+            .unwrap()
+            .ack_state(local, map.state())
+            .unwrap();
+        map.remove("hot");
+        let stats = map.stats();
+        assert_eq!((stats.live, stats.tombstones), (3, 1));
+        assert_eq!(map.seq_of("a"), None);
+        assert_eq!(map.seq_of("hot"), Some(6));
+        assert_eq!(map.get("b"), Some(&2));
+
+        // The tombstone that could not be purged is looked at again once there are twice as many
+        // tombstones
+        for replica in [local, remote] {
+            tracker.lock().unwrap().ack(replica, "hot", 6).unwrap();
+        }
+        map.remove("b");
+        let stats = map.stats();
+        assert_eq!((stats.live, stats.tombstones), (2, 1));
+        assert_eq!(map.seq_of("hot"), None);
+        assert_eq!(map.seq_of("b"), Some(2));
+    }
+
+    #[test]
+    fn read_through_projection() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct User {
+            name: String,
+            age: u32,
+        }
+
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct UserName<'a>(&'a str);
+
+        let mut map = LWWMap::new();
+        for (id, name, age) in [(1, "alice", 30), (2, "bob", 40), (3, "carol", 50)] {
+            let name = name.to_string();
+            map.insert(id, User { name, age });
+        }
+        map.remove(&2);
+
+        let mut names: Vec<_> = map.iter_as(|u| UserName(&u.name)).collect();
+        names.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(
+            names,
+            vec![(&1, UserName("alice")), (&3, UserName("carol"))]
+        );
+
+        assert_eq!(map.get_as(&3, |u| u.age), Some(50));
+        assert_eq!(map.get_as(&2, |u| u.age), None);
+    }
+
+    #[test]
+    fn merge_of_hostile_state_does_not_panic() {
+        let max = |entry| {
+            let state = LWWState::from_parts(Some(entry), u64::MAX, ReplicaId::new(0xBAD)).unwrap();
+            LWWRegister::from_state(state)
+        };
+
+        let mut map = LWWMap::new();
+        map.insert("live", 0xC0FFEE);
+        map.insert("gone", 0xC0FFEE);
+
+        let hostile = MapState::from_inner(
+            [
+                ("live", max(Entry::Occupied(0xBAD))),
+                ("gone", max(Entry::Tombstoned)),
+                ("new", max(Entry::Occupied(0xBAD))),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        map.merge(hostile);
+        let merged = map.clone();
+
+        // Local writes to the keys that reached the highest sequence number are rejected, rather
+        // than leaving replicas with different values under the same version
+        assert_eq!(
+            map.try_insert("live", 0xF00D),
+            Err(WriteError::SeqExhausted)
+        );
+        assert_eq!(map.try_remove("new"), Err(WriteError::SeqExhausted));
+        assert_eq!(map.remove_many(["live", "new"]), 0);
+        assert!(!map.soft_remove("live"));
+        assert_eq!(map.rename("live", "other"), Err(RenameError::SeqExhausted));
+        assert_eq!(map, merged);
+        assert_eq!(map.digest(), merged.digest());
+        assert_eq!(map.get("live"), Some(&0xBAD));
+
+        // Other keys can still be written
+        map.insert("other", 0xF00D);
+        assert_eq!(map.get("other"), Some(&0xF00D));
+    }
+
+    #[test]
+    fn concurrent_fresh_inserts_are_reported() {
+        let mut a = LWWMap::with_replica(ReplicaId::new(1));
+        let mut b = LWWMap::with_replica(ReplicaId::new(2));
+        a.insert("fresh", 0xBAD);
+        b.insert("fresh", 0xC0FFEE);
+
+        assert_converges!(a, b);
+
+        let summary = a.clone().merge_summary(b.clone().take());
+        assert_eq!(summary.conflicts_resolved, 1);
+
+        let losers = a.merge_capturing_losers(b.clone().take());
+        let [conflict] = &losers[..] else {
+            panic!("expected a single conflict, got {losers:?}");
+        };
+        assert_eq!((conflict.key, conflict.value), ("fresh", 0xBAD));
+        assert_eq!(conflict.winner.replica, ReplicaId::new(2));
+        assert_eq!(conflict.loser.replica, ReplicaId::new(1));
+        assert_eq!((conflict.winner.seq, conflict.loser.seq), (1, 1));
+        assert_eq!(a.get("fresh"), Some(&0xC0FFEE));
+    }
+
+    #[test]
+    fn batch_reads_skip_tombstones() {
+        let mut map: LWWMap<_, _> = [("a", 0xC0FFEE), ("b", 0xBAD), ("gone", 0xDEAD)]
+            .into_iter()
+            .collect();
+        map.remove("gone");
+
+        assert_eq!(
+            map.get_many(&["b", "absent", "gone", "a"]),
+            vec![Some(&0xBAD), None, None, Some(&0xC0FFEE)]
+        );
+
+        assert!(map.contains_all(&["a", "b"]));
+        assert!(!map.contains_all(&["a", "gone"]));
+        assert!(!map.contains_all(&["absent"]));
+        assert!(map.contains_all::<str>(&[]));
+    }
+
+    #[test]
+    fn entry_or_default_writes_on_mutation() {
+        let mut map: LWWMap<_, Vec<u32>> = LWWMap::new();
+        map.insert("live", vec![0xC0FFEE]);
+        map.insert("gone", vec![0xDEAD]);
+        map.remove("gone");
+
+        assert!(map.entry_or_default("absent").is_empty());
+        assert_eq!(map.seq_of("absent"), Some(1));
+
+        // Reading a live value does not write it
+        assert_eq!(*map.entry_or_default("live"), vec![0xC0FFEE]);
+        assert_eq!(map.seq_of("live"), Some(1));
+
+        {
+            let mut live = map.entry_or_default("live");
+            live.push(0xBAD);
+            live.push(0xF00D);
+        }
+        assert_eq!(map.get("live"), Some(&vec![0xC0FFEE, 0xBAD, 0xF00D]));
+        assert_eq!(map.seq_of("live"), Some(2));
+
+        map.entry_or_default("gone").push(0xF00D);
+        assert_eq!(map.get("gone"), Some(&vec![0xF00D]));
+        assert_eq!(map.seq_of("gone"), Some(4));
+
+        let mut other = LWWMap::new();
+        other.merge(map.clone().take());
+        assert_eq!(other, map);
+        assert_eq!(other.digest(), map.digest());
+    }
+
+    #[test]
+    fn entry_or_default_rolls_back_rejected_writes() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let mut map = LWWMap::with_max_value_bytes(2, Vec::len);
+        map.insert("key", vec![0xC0]);
+        let digest = map.digest();
+
+        let oversized = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut v = map.entry_or_default("key");
+            v.push(0xFF);
+            v.push(0xEE);
+        }));
+        assert!(oversized.is_err());
+        assert_eq!(map.get("key"), Some(&vec![0xC0]));
+        assert_eq!(map.seq_of("key"), Some(1));
+        assert_eq!(map.digest(), digest);
+
+        map.entry_or_default("key").push(0xFF);
+        assert_eq!(map.get("key"), Some(&vec![0xC0, 0xFF]));
+
+        let mut map = LWWMap::new();
+        map.set_version_extractor(|v: &Vec<u64>| v.len() as u64);
+        map.insert("key", vec![1, 2]);
+        let regressed = panic::catch_unwind(AssertUnwindSafe(|| {
+            map.entry_or_default("key").clear();
+        }));
+        assert!(regressed.is_err());
+        assert_eq!(map.get("key"), Some(&vec![1, 2]));
+        assert_eq!(map.seq_of("key"), Some(1));
+        map.check_valid();
+    }
+
+    #[test]
+    fn reserve_avoids_reallocations() {
+        let mut map = LWWMap::new();
+        map.insert(0, 0xC0FFEE);
+        map.remove(&0);
+
+        map.reserve(100);
+        let capacity = map.capacity();
+        assert!(capacity >= 101);
+
+        // The tombstone takes a slot as well
+        for k in 1..capacity as u64 {
+            map.insert(k, 0xBAD);
+        }
+        assert_eq!(map.capacity(), capacity);
+
+        let map: LWWMap<u64, u64> = LWWMap::with_capacity(100);
+        assert!(map.capacity() >= 100);
+    }
+
+    #[test]
+    fn state_from_versions() {
+        let (a, b) = (ReplicaId::new(1), ReplicaId::new(2));
+        let state = MapState::from_versions([
+            ("live", Entry::Occupied(0xC0FFEE), 3, a),
+            ("removed", Entry::Tombstoned, 2, b),
+            ("invalid", Entry::Occupied(0xBAD), 0, a),
+        ]);
+
+        let inner = state.clone().into_inner();
+        assert_eq!(inner.len(), 2);
+        assert_eq!(inner["live"].version(), (3, a, 0));
+        assert_eq!(inner["removed"].version(), (2, b, 2));
+
+        let map = LWWMap::from(state);
+        assert_eq!(map.get("live"), Some(&0xC0FFEE));
+        assert!(!map.contains_key("removed"));
+    }
+
+    #[test]
+    fn merge_ref_shares_winning_values() {
+        use std::sync::Arc;
+
+        let big = Arc::new(vec![0xC0FFEE_u32; 1024]);
+        let stale = Arc::new(vec![0xBAD]);
+
+        let mut a = LWWMap::new();
+        a.insert("stale", Arc::new(vec![0xF00D]));
+        a.insert("stale", Arc::new(vec![0xF00D]));
+
+        let mut b = LWWMap::new();
+        b.insert("big", Arc::clone(&big));
+        b.insert("stale", Arc::clone(&stale));
+
+        a.merge_ref(b.state());
+        assert!(Arc::ptr_eq(a.get("big").unwrap(), &big));
+        assert_eq!(Arc::strong_count(&big), 3);
+
+        // The losing value is not cloned
+        assert_eq!(Arc::strong_count(&stale), 2);
+        assert_eq!(a.get("stale"), Some(&Arc::new(vec![0xF00D])));
+
+        let mut expected = a.clone();
+        expected.merge(b.clone().take());
+        assert_eq!(a, expected);
+    }
+
+    #[test]
+    fn version_fence_rejects_regressions() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Doc {
+            version: u64,
+            body: &'static str,
+        }
+
+        let doc = |version, body| Doc { version, body };
+
+        let mut a = LWWMap::new();
+        a.set_version_extractor(|d: &Doc| d.version);
+        a.insert("doc", doc(2, "v2"));
+
+        let mut b = LWWMap::new();
+        for _ in 0..5 {
+            b.insert("doc", doc(1, "v1"));
+        }
+        b.insert("new", doc(1, "new"));
+
+        // A higher seq does not let an older version in
+        let summary = a.merge_summary(b.clone().take());
+        assert_eq!(a.get("doc"), Some(&doc(2, "v2")));
+        assert_eq!(a.get("new"), Some(&doc(1, "new")));
+        assert_eq!((summary.inserted, summary.updated), (1, 0));
+
+        assert_eq!(
+            a.try_insert("doc", doc(1, "stale")),
+            Err(WriteError::VersionRegression)
+        );
+        assert_eq!(a.try_insert("doc", doc(2, "edit")), Ok(Some(doc(2, "v2"))));
+        assert_eq!(a.get("doc"), Some(&doc(2, "edit")));
+
+        // Removals are not fenced, and a removed key accepts any version
+        a.remove("doc");
+        assert_eq!(a.try_insert("doc", doc(0, "again")), Ok(None));
+    }
+
+    #[test]
+    fn all_keys_include_tombstones() {
+        let mut map: LWWMap<_, _> = [("live", 0xC0FFEE), ("removed", 0xBAD), ("trashed", 0xF00D)]
+            .into_iter()
+            .collect();
+        map.remove("removed");
+        map.soft_remove("trashed");
+
+        let mut all: Vec<_> = map.all_keys().collect();
+        all.sort();
+
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"live"]);
+        assert_eq!(all, vec![&"live", &"removed", &"trashed"]);
+        assert_eq!(map.tombstoned_keys().collect::<Vec<_>>(), vec![&"removed"]);
+    }
+
+    #[test]
+    fn merge_converted_state() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct User {
+            name: String,
+            admin: bool,
+        }
+
+        let mut old = LWWMap::new();
+        old.insert("alice", "Alice".to_string());
+        old.insert("bob", "Bob".to_string());
+        old.insert("bob", "Robert".to_string());
+        old.insert("carol", "Carol".to_string());
+        old.remove("carol");
+
+        let mut new = LWWMap::new();
+        let alice = User {
+            name: "Alice".to_string(),
+            admin: true,
+        };
+        new.insert("alice", alice.clone());
+        new.insert("alice", alice.clone());
+        new.insert("carol", alice.clone());
+
+        new.merge_converted(old.take(), |name| User { name, admin: false });
+
+        assert_eq!(new.get("alice"), Some(&alice));
+        assert_eq!(new.get("bob").map(|u| u.name.as_str()), Some("Robert"));
+        assert_eq!(new.seq_of("bob"), Some(2));
+        assert!(!new.contains_key("carol"));
+    }
+
+    #[test]
+    fn merge_by_keeps_richer_value() {
+        let fields = |v: &[Option<u32>; 3]| v.iter().flatten().count();
+        let richer = |a: &[Option<u32>; 3], b: &[Option<u32>; 3]| fields(a).cmp(&fields(b));
+
+        let full = [Some(0xC0FFEE), Some(0xBAD), Some(0xF00D)];
+        let partial = [Some(0xC0FFEE), None, None];
+
+        let mut a = LWWMap::with_replica(ReplicaId::new(1));
+        a.insert("record", full);
+        a.insert("other", partial);
+
+        let mut b = LWWMap::with_replica(ReplicaId::new(2));
+        for _ in 0..3 {
+            b.insert("record", partial);
+        }
+        b.insert("other", full);
+        b.insert("only", partial);
+        let peer = b.clone();
+
+        let state = b.clone().take();
+        b.merge_by(a.clone().take(), richer);
+        a.merge_by(state, richer);
+
+        // The richer value is written again above the seq of the more recent one
+        assert_eq!(a.get("record"), Some(&full));
+        assert_eq!(a.seq_of("record"), Some(4));
+        assert_eq!(a.get("other"), Some(&full));
+        assert_eq!(a.get("only"), Some(&partial));
+        assert_converges!(a, b);
+
+        // A peer that only syncs with plain merges does not undo it, and converges to it
+        let mut synced = a.clone();
+        synced.merge(peer.clone().take());
+        assert_eq!(synced, a);
+
+        let mut peer = peer;
+        peer.merge(a.clone().take());
+        assert_eq!(peer.get("record"), Some(&full));
+        assert_converges!(a, peer);
+
+        // The order of the merges matters once a removal is involved
+        let version = |entry, seq, id| {
+            let state = LWWState::from_parts(Some(entry), seq, ReplicaId::new(id)).unwrap();
+            MapState::from_inner(
+                [("key", LWWRegister::from_state(state))]
+                    .into_iter()
+                    .collect(),
+            )
+        };
+        let states = [
+            version(Entry::Occupied(full), 2, 1),
+            version(Entry::Occupied(partial), 4, 2),
+            version(Entry::Tombstoned, 3, 3),
+        ];
+        let merge_in = |order: [usize; 3]| {
+            let mut map = LWWMap::from(states[order[0]].clone());
+            map.merge_by(states[order[1]].clone(), richer);
+            map.merge_by(states[order[2]].clone(), richer);
+            map
+        };
+
+        let (abc, cab) = (merge_in([0, 1, 2]), merge_in([2, 0, 1]));
+        assert_eq!(abc.get("key"), Some(&full));
+        assert_eq!(cab.get("key"), Some(&partial));
+        assert_converges!(abc, cab);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid map: the digest does not match the keys")]
+    fn validator_catches_stale_digest() {
+        let mut map: LWWMap<_, _> = [("a", 0xC0FFEE), ("b", 0xBAD)].into_iter().collect();
+        map.check_valid();
+
+        // Corrupt the map behind the back of the incremental digest
+        map.state.inner.remove("a");
+        map.check_valid();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "invalid map: the digest does not match the keys")]
+    fn merges_validate_the_map_with_debug_assertions() {
+        let mut map: LWWMap<_, _> = [("a", 0xC0FFEE), ("b", 0xBAD)].into_iter().collect();
+
+        // Corrupt the map behind the back of the incremental digest
+        map.state.inner.remove("a");
+        map.merge(LWWMap::new().take());
+    }
+
+    #[test]
+    fn concurrent_keys_without_merging() {
+        let mut a = LWWMap::with_replica(ReplicaId::new(1));
+        a.insert("sequential", 0xC0FFEE);
+        a.insert("concurrent", 0xC0FFEE);
+        a.insert("removed", 0xC0FFEE);
+
+        let mut b = a.fork(ReplicaId::new(2));
+        b.insert("sequential", 0xBAD);
+        a.merge(b.clone().take());
+        a.insert("sequential", 0xF00D);
+        assert!(a.concurrent_keys(b.state()).is_empty());
+
+        a.insert("concurrent", 0xF00D);
+        b.insert("concurrent", 0xBAD);
+        a.remove("removed");
+        b.insert("removed", 0xBAD);
+
+        let before = a.clone();
+        let mut keys = a.concurrent_keys(b.state());
+        keys.sort();
+        assert_eq!(keys, vec!["concurrent", "removed"]);
+        assert_eq!(a, before);
+    }
+
+    #[test]
+    fn map_values_keeps_versions() {
+        let mut map = LWWMap::with_replica(ReplicaId::new(3));
+        map.insert("a", 2);
+        map.insert("a", 3);
+        map.insert("b", 5);
+        map.insert("gone", 7);
+        map.remove("gone");
+        map.insert("trash", 11);
+        map.soft_remove("trash");
+
+        let squares = map.map_values(|v| v * v);
+        assert_eq!(squares.get("a"), Some(&9));
+        assert_eq!(squares.get("b"), Some(&25));
+        assert!(!squares.contains_key("gone"));
+        assert_eq!(squares.get_trashed("trash"), Some(&121));
+        for k in ["a", "b", "gone", "trash"] {
+            assert_eq!(squares.seq_of(k), map.seq_of(k));
+        }
+        assert_eq!(squares.replica(), ReplicaId::new(3));
+
+        // The derived map merges like its source
+        let mut other = map.clone();
+        other.insert("b", 6);
+        other.insert("gone", 8);
+        let mut derived = squares.clone();
+        derived.merge(other.map_values(|v| v * v).take());
+        map.merge(other.take());
+        assert_eq!(derived, map.map_values(|v| v * v));
+    }
+
+    #[test]
+    fn clone_into_keeps_capacity() {
+        let mut src = LWWMap::new();
+        src.insert(0xC0FFEE, 0xBAD);
+        src.insert(0xF00D, 0xDEAD);
+        src.remove(&0xF00D);
+
+        let mut dst = LWWMap::new();
+        dst.reserve(256);
+        dst.insert(0xDEAD, 0xF00D);
+        let capacity = dst.capacity();
+
+        src.clone_into(&mut dst);
+        assert_eq!(dst, src);
+        assert_eq!(dst.digest(), src.digest());
+        assert!(dst.get(&0xDEAD).is_none());
+        assert_eq!(dst.capacity(), capacity);
+
+        src.insert(0xDEAD, 0xC0FFEE);
+        src.clone_into(&mut dst);
+        assert_eq!(dst, src);
+        assert_eq!(dst.capacity(), capacity);
+    }
+
+    #[test]
+    fn equal_concurrent_values_do_not_conflict() {
+        let mut a = LWWMap::with_replica(ReplicaId::new(1));
+        let mut b = LWWMap::with_replica(ReplicaId::new(2));
+        a.insert("fresh", 0xC0FFEE);
+        b.insert("fresh", 0xC0FFEE);
+        a.insert("other", 0xBAD);
+        b.insert("other", 0xF00D);
+
+        assert_eq!(a.concurrent_keys(b.state()), vec!["other"]);
+
+        let mut ab = a.clone();
+        let losers = ab.merge_capturing_losers(b.clone().take());
+        assert_eq!(losers.len(), 1);
+        assert_eq!(losers[0].key, "other");
+
+        let mut ba = b.clone();
+        let losers = ba.merge_capturing_losers(a.clone().take());
+        assert_eq!(losers.len(), 1);
+        assert_eq!(losers[0].key, "other");
+
+        assert_eq!(ab, ba);
+        assert_eq!(ab.get("fresh"), Some(&0xC0FFEE));
+
+        // Summaries do not compare values, equal concurrent values still count as a conflict
+        assert_eq!(b.merge_summary(a.take()).conflicts_resolved, 2);
+    }
+}
@@ -0,0 +1,102 @@
+//! Module that defines metadata about who wrote the values of a [`LWWMap`], and when.
+//!
+//! The metadata is stored along with the value it describes, which means that it is merged along
+//! with the value: after a merge, the metadata of a key is always the metadata of the write that
+//! won. Maps that don't need metadata don't pay for it
+
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+use crate::crdt::replica::ReplicaId;
+
+use super::map::LWWMap;
+
+/// Describes who wrote a value, and when
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Meta {
+    /// The replica that wrote the value
+    pub replica: ReplicaId,
+
+    /// When the value has been written, in a unit chosen by the application
+    pub timestamp: u64,
+}
+
+/// A value along with the metadata of the write that produced it.
+/// Metadata of another type than [`Meta`] is written as given by the application, with
+/// [`LWWMap::insert`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WithMeta<V, M = Meta> {
+    pub value: V,
+
+    pub meta: M,
+}
+
+impl<K, V> LWWMap<K, WithMeta<V>>
+where
+    K: Eq + Hash,
+{
+    /// Inserts a key-value pair into the map, along with the metadata of the write: the replica of
+    /// this map, see [`LWWMap::replica`], and `timestamp`.
+    /// See [`LWWMap::insert`]
+    pub fn insert_with_meta(&mut self, k: K, value: V, timestamp: u64) -> Option<V> {
+        let meta = Meta {
+            replica: self.replica(),
+            timestamp,
+        };
+
+        self.insert(k, WithMeta { value, meta }).map(|v| v.value)
+    }
+}
+
+impl<K, V, M> LWWMap<K, WithMeta<V, M>>
+where
+    K: Eq + Hash,
+{
+    /// Returns a reference to the value corresponding to the key, without its metadata.
+    pub fn get_value<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(k).map(|v| &v.value)
+    }
+
+    /// Returns a reference to the metadata of the write that produced the value corresponding to
+    /// the key.
+    pub fn meta_of<Q>(&self, k: &Q) -> Option<&M>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(k).map(|v| &v.meta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::lww::map::LWWMap;
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::CRDT;
+
+    use super::Meta;
+
+    #[test]
+    fn meta_of_the_winning_write_is_kept() {
+        let mut a = LWWMap::with_replica(ReplicaId::new(1));
+        a.insert_with_meta("title", "draft", 100);
+
+        let mut b = a.fork(ReplicaId::new(2));
+        b.insert_with_meta("title", "final", 200);
+
+        a.merge(b.take());
+
+        let bob = Meta {
+            replica: ReplicaId::new(2),
+            timestamp: 200,
+        };
+        assert_eq!(a.get_value("title"), Some(&"final"));
+        assert_eq!(a.meta_of("title"), Some(&bob));
+        assert_eq!(a.register("title").unwrap().replica(), bob.replica);
+        assert_eq!(a.meta_of("absent"), None);
+    }
+}
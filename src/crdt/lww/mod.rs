@@ -1,2 +1,9 @@
+pub mod clock;
 pub mod map;
+pub mod meta;
+pub mod namespace;
+pub mod ordered;
+pub mod recorder;
 pub mod register;
+pub mod sync;
+pub mod timestamped;
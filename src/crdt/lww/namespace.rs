@@ -0,0 +1,143 @@
+//! Module that defines a namespaced view over a [`LWWMap`] with string keys.
+//! This lets multiple logical maps share the same [`LWWMap`] and converge with a single merge
+
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+use super::map::LWWMap;
+
+/// The separator between the namespace and the key in the backing map
+pub const SEPARATOR: char = '/';
+
+/// A view over the keys of a [`LWWMap`] that belong to a namespace.
+/// Keys are transparently prefixed with the namespace when accessing the backing map
+pub struct Namespaced<'a, K, V> {
+    map: &'a mut LWWMap<K, V>,
+
+    prefix: String,
+}
+
+impl<K, V> LWWMap<K, V>
+where
+    K: Eq + Hash + Borrow<str> + From<String>,
+{
+    /// Returns a view over the keys of this map that belong to the namespace `name`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` contains [`SEPARATOR`], as it would make namespaces collide
+    pub fn namespace(&mut self, name: &str) -> Namespaced<'_, K, V> {
+        assert!(
+            !name.contains(SEPARATOR),
+            "namespace {name:?} must not contain {SEPARATOR:?}"
+        );
+
+        Namespaced {
+            map: self,
+            prefix: format!("{name}{SEPARATOR}"),
+        }
+    }
+}
+
+impl<K, V> Namespaced<'_, K, V>
+where
+    K: Eq + Hash + Borrow<str> + From<String>,
+{
+    fn key(&self, k: &str) -> String {
+        format!("{}{k}", self.prefix)
+    }
+
+    /// Returns a reference to the value corresponding to the key in this namespace.
+    pub fn get(&self, k: &str) -> Option<&V> {
+        self.map.get(self.key(k).as_str())
+    }
+
+    /// Inserts a key-value pair in this namespace.
+    /// See [`LWWMap::insert`]
+    pub fn insert(&mut self, k: &str, v: V) -> Option<V> {
+        let k = self.key(k);
+        self.map.insert(K::from(k), v)
+    }
+
+    /// Removes a key from this namespace.
+    /// See [`LWWMap::remove`]
+    pub fn remove(&mut self, k: &str) -> Option<V> {
+        let k = self.key(k);
+        self.map.remove(k.as_str())
+    }
+
+    /// Returns `true` if this namespace contains a value for the specified key.
+    pub fn contains_key(&self, k: &str) -> bool {
+        self.map.contains_key(self.key(k).as_str())
+    }
+
+    /// Returns an iterator over the live entries of this namespace, with the namespace stripped
+    /// from their key
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &V)> {
+        self.map.iter().filter_map(|(k, v)| {
+            k.borrow()
+                .strip_prefix(self.prefix.as_str())
+                .map(|k| (k, v))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::lww::map::LWWMap;
+    use crate::crdt::CRDT;
+
+    fn sorted<'a>(iter: impl Iterator<Item = (&'a str, &'a u32)>) -> Vec<(String, u32)> {
+        let mut entries: Vec<_> = iter.map(|(k, v)| (k.to_string(), *v)).collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn namespaces_are_isolated() {
+        let mut map = LWWMap::<String, u32>::new();
+
+        map.namespace("users").insert("1", 0xC0FFEE);
+        map.namespace("groups").insert("1", 0xBAD);
+        map.namespace("groups").insert("2", 0xF00D);
+
+        assert_eq!(map.namespace("users").get("1"), Some(&0xC0FFEE));
+        assert_eq!(map.namespace("groups").get("1"), Some(&0xBAD));
+        assert!(!map.namespace("users").contains_key("2"));
+
+        map.namespace("groups").remove("1");
+        assert_eq!(map.namespace("users").get("1"), Some(&0xC0FFEE));
+        assert_eq!(
+            sorted(map.namespace("groups").iter()),
+            vec![("2".to_string(), 0xF00D)]
+        );
+    }
+
+    #[test]
+    fn namespaces_converge_with_a_single_merge() {
+        let mut a = LWWMap::<String, u32>::new();
+        a.namespace("users").insert("1", 0xC0FFEE);
+        a.namespace("groups").insert("1", 0xBAD);
+
+        let mut b = LWWMap::<String, u32>::new();
+        b.namespace("users").insert("2", 0xF00D);
+        b.namespace("groups").insert("2", 0xDEAD);
+
+        a.merge(b.take());
+
+        assert_eq!(
+            sorted(a.namespace("users").iter()),
+            vec![("1".to_string(), 0xC0FFEE), ("2".to_string(), 0xF00D)]
+        );
+        assert_eq!(
+            sorted(a.namespace("groups").iter()),
+            vec![("1".to_string(), 0xBAD), ("2".to_string(), 0xDEAD)]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn namespace_with_separator() {
+        LWWMap::<String, u32>::new().namespace("users/1");
+    }
+}
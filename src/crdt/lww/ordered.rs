@@ -0,0 +1,283 @@
+//! Module that defines an ordered `Map` of [`LWWRegister`] values
+
+use std::borrow::Borrow;
+use std::collections::{btree_map, BTreeMap};
+use std::ops::{Bound, RangeBounds};
+
+use crate::crdt::CRDT;
+
+use super::map::Entry;
+use super::register::LWWRegister;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderedMapState<K, V> {
+    inner: BTreeMap<K, LWWRegister<Entry<V>>>,
+}
+
+/// A map of [`LWWRegister`] values whose keys are kept sorted.
+/// This behaves exactly like [`super::map::LWWMap`] but iterates over its entries in key order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderedLWWMap<K, V> {
+    state: OrderedMapState<K, V>,
+}
+
+impl<K, V> OrderedLWWMap<K, V> {
+    /// Create a new, empty map
+    pub fn new() -> Self {
+        Self {
+            state: OrderedMapState {
+                inner: BTreeMap::new(),
+            },
+        }
+    }
+}
+
+impl<K, V> Default for OrderedLWWMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> OrderedLWWMap<K, V>
+where
+    K: Ord,
+{
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.state.inner.get(k).and_then(|reg| reg.value().get())
+    }
+
+    /// Inserts a key-value pair into the map.
+    /// If the map did not have this key present, [`None`] is returned.
+    /// If the map did have this key present, the register holding the value is updated, and the old value is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sequence number of the key reached [`u64::MAX`], see
+    /// [`LWWRegister::try_update`]
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        match self.state.inner.entry(k) {
+            btree_map::Entry::Occupied(mut e) => e.get_mut().update(Entry::Occupied(v)).take(),
+            btree_map::Entry::Vacant(e) => {
+                e.insert(LWWRegister::new(Entry::Occupied(v)));
+                None
+            }
+        }
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key was previously live.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sequence number of the key reached [`u64::MAX`], see
+    /// [`LWWRegister::try_update`]
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.state
+            .inner
+            .get_mut(k)
+            .and_then(|e| e.update(Entry::Tombstoned).take())
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.state
+            .inner
+            .get(k)
+            .map(|e| !e.value().is_tombstoned())
+            .unwrap_or(false)
+    }
+
+    /// Returns an iterator over the live entries of the map, sorted by key
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.state
+            .inner
+            .iter()
+            .filter_map(|(k, reg)| reg.value().get().map(|v| (k, v)))
+    }
+
+    /// Returns an iterator over the live entries of the map whose key falls in `range`, sorted by
+    /// key
+    pub fn range<Q, R>(&self, range: R) -> impl Iterator<Item = (&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.state
+            .inner
+            .range(range)
+            .filter_map(|(k, reg)| reg.value().get().map(|v| (k, v)))
+    }
+}
+
+impl<K, V> CRDT for OrderedLWWMap<K, V>
+where
+    K: Ord,
+{
+    type State = OrderedMapState<K, V>;
+
+    fn merge(&mut self, other: Self::State) {
+        for (k, v) in other.inner {
+            match self.state.inner.entry(k) {
+                btree_map::Entry::Occupied(mut e) => e.get_mut().merge_entry(CRDT::take(v)),
+                btree_map::Entry::Vacant(e) => {
+                    e.insert(v);
+                }
+            }
+        }
+    }
+
+    fn take(self) -> Self::State {
+        self.state
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for OrderedLWWMap<K, V>
+where
+    K: Ord,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let iter = iter
+            .into_iter()
+            .map(|(k, v)| (k, LWWRegister::new(Entry::Occupied(v))));
+
+        Self {
+            state: OrderedMapState {
+                inner: iter.collect(),
+            },
+        }
+    }
+}
+
+/// A position in an [`OrderedLWWMap`] that does not borrow the map.
+///
+/// A cursor remembers the last key it returned and takes a fresh borrow of the map every time it
+/// is advanced, so that the map can be written to or merged into between two calls to
+/// [`Cursor::next`] and iteration resumed afterwards.
+///
+/// Every call to [`Cursor::next`] observes the map as it is at that time:
+/// - keys that are inserted after the position of the cursor will be returned
+/// - keys that are inserted before the position of the cursor will be missed
+/// - keys that are removed before the cursor reached them will be skipped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor<K> {
+    last: Option<K>,
+}
+
+impl<K> Cursor<K> {
+    /// Create a cursor positioned before the first key of a map
+    pub fn new() -> Self {
+        Self { last: None }
+    }
+}
+
+impl<K> Default for Cursor<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Cursor<K>
+where
+    K: Ord + Clone,
+{
+    /// Advance the cursor to the next live entry of `map` and return it.
+    /// Returns [`None`] once the cursor went past the last key of the map
+    pub fn next<'a, V>(&mut self, map: &'a OrderedLWWMap<K, V>) -> Option<(&'a K, &'a V)> {
+        let lower = self.last.as_ref().map_or(Bound::Unbounded, Bound::Excluded);
+        let (k, v) = map.range::<K, _>((lower, Bound::Unbounded)).next()?;
+
+        self.last = Some(k.clone());
+        Some((k, v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::CRDTExt;
+
+    use super::{Cursor, OrderedLWWMap};
+
+    #[test]
+    fn iter_is_sorted_after_merge() {
+        let mut a: OrderedLWWMap<_, _> = [(3, "c"), (1, "a"), (5, "e")].into_iter().collect();
+        let mut b: OrderedLWWMap<_, _> = [(4, "d"), (2, "b"), (5, "e")].into_iter().collect();
+
+        b.remove(&5);
+        b.merge_into(&mut a);
+
+        assert_eq!(
+            a.iter().collect::<Vec<_>>(),
+            vec![(&1, &"a"), (&2, &"b"), (&3, &"c"), (&4, &"d")]
+        );
+    }
+
+    #[test]
+    fn range_skips_tombstones() {
+        let mut map: OrderedLWWMap<_, _> = (0..10).map(|i| (i, i * 10)).collect();
+        map.remove(&4);
+
+        let mut other = OrderedLWWMap::new();
+        other.insert(6, 0xBAD);
+        other.insert(6, 0xF00D);
+        other.merge_into(&mut map);
+
+        assert_eq!(
+            map.range(3..7).collect::<Vec<_>>(),
+            vec![(&3, &30), (&5, &50), (&6, &0xF00D)]
+        );
+        assert_eq!(
+            map.range(7..=9).collect::<Vec<_>>(),
+            vec![(&7, &70), (&8, &80), (&9, &90)]
+        );
+        assert_eq!(map.range(..2).count(), 2);
+    }
+
+    #[test]
+    fn cursor_resumes_across_writes() {
+        let mut map: OrderedLWWMap<_, _> = [(1, "a"), (3, "c"), (5, "e")].into_iter().collect();
+        let mut cursor = Cursor::new();
+
+        assert_eq!(cursor.next(&map), Some((&1, &"a")));
+
+        // Keys after the cursor are seen, keys before it are missed
+        map.insert(0, "missed");
+        map.insert(2, "b");
+        assert_eq!(cursor.next(&map), Some((&2, &"b")));
+
+        let mut other = OrderedLWWMap::new();
+        other.insert(4, "d");
+        other.merge_into(&mut map);
+        map.remove(&3);
+
+        assert_eq!(cursor.next(&map), Some((&4, &"d")));
+        assert_eq!(cursor.next(&map), Some((&5, &"e")));
+        assert_eq!(cursor.next(&map), None);
+
+        map.insert(6, "f");
+        assert_eq!(cursor.next(&map), Some((&6, &"f")));
+    }
+
+    #[test]
+    fn merge_into_empty_keeps_versions() {
+        let mut map = OrderedLWWMap::new();
+        map.insert(1, "a");
+        map.insert(1, "b");
+        map.insert(2, "c");
+        map.remove(&2);
+
+        assert_converges!(map, OrderedLWWMap::new());
+    }
+}
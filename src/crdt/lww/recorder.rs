@@ -0,0 +1,202 @@
+//! Module that defines a recorder of the operations applied to a [`LWWMap`], to reproduce
+//! divergences by replaying them
+
+use std::hash::Hash;
+
+use crate::crdt::replica::ReplicaId;
+use crate::crdt::CRDT;
+
+use super::map::{LWWMap, MapState, WriteId};
+
+/// An operation that has been applied to a [`LWWMap`]
+#[derive(Debug, Clone)]
+pub enum Operation<K, V> {
+    /// A local insertion, along with the version of the key it wrote
+    Insert { key: K, value: V, write: WriteId },
+
+    /// A local removal, along with the version of the key after the removal if it was known
+    Remove { key: K, write: Option<WriteId> },
+
+    /// A merge of the state of another replica
+    Merge(MapState<K, V>),
+}
+
+/// A [`LWWMap`] that records every operation applied to it.
+/// The recorded operations can be replayed with [`replay`] to deterministically rebuild the map
+pub struct OperationRecorder<K, V> {
+    map: LWWMap<K, V>,
+
+    ops: Vec<Operation<K, V>>,
+}
+
+impl<K, V> OperationRecorder<K, V> {
+    /// Create a recorder around a new, empty map
+    pub fn new() -> Self {
+        Self::with_replica(ReplicaId::default())
+    }
+
+    /// Create a recorder around a new, empty map whose local writes are performed by `replica`,
+    /// see [`LWWMap::with_replica`]
+    pub fn with_replica(replica: ReplicaId) -> Self {
+        Self {
+            map: LWWMap::with_replica(replica),
+            ops: Vec::new(),
+        }
+    }
+
+    /// Returns the recorded map
+    pub fn map(&self) -> &LWWMap<K, V> {
+        &self.map
+    }
+
+    /// Returns the operations that have been recorded so far
+    pub fn operations(&self) -> &[Operation<K, V>] {
+        &self.ops
+    }
+
+    /// Consume the recorder and return the map along with the recorded operations
+    pub fn into_parts(self) -> (LWWMap<K, V>, Vec<Operation<K, V>>) {
+        (self.map, self.ops)
+    }
+}
+
+impl<K, V> Default for OperationRecorder<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> OperationRecorder<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Inserts a key-value pair into the map and record it, see [`LWWMap::insert`]
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let old = self.map.insert(k.clone(), v.clone());
+        self.ops.push(Operation::Insert {
+            write: self.write_of(&k).expect("key has just been inserted"),
+            key: k,
+            value: v,
+        });
+        old
+    }
+
+    /// Removes a key from the map and record it, see [`LWWMap::remove`]
+    pub fn remove(&mut self, k: K) -> Option<V> {
+        let old = self.map.remove(&k);
+        self.ops.push(Operation::Remove {
+            write: self.write_of(&k),
+            key: k,
+        });
+        old
+    }
+
+    /// Returns the version of `k` in the map, if it is known
+    fn write_of(&self, k: &K) -> Option<WriteId> {
+        self.map.register(k).map(|reg| WriteId {
+            replica: reg.replica(),
+            seq: reg.seq(),
+        })
+    }
+
+    /// Merge the state of another replica into the map and record it
+    pub fn merge(&mut self, other: MapState<K, V>) {
+        self.ops.push(Operation::Merge(other.clone()));
+        self.map.merge(other);
+    }
+}
+
+/// Rebuild a map by applying `ops`, in order, to a new, empty map.
+/// Local writes are applied at the version they have been recorded with, so the rebuilt map holds
+/// the same versions as the recorded one, whatever replica recorded them
+pub fn replay<K, V>(ops: impl IntoIterator<Item = Operation<K, V>>) -> LWWMap<K, V>
+where
+    K: Eq + Hash,
+{
+    let mut map = LWWMap::new();
+    for op in ops {
+        match op {
+            Operation::Insert { key, value, write } => {
+                map.apply_remote_update(key, Some(value), write.seq, write.replica);
+            }
+            Operation::Remove {
+                key,
+                write: Some(write),
+            } => {
+                map.apply_remote_update(key, None, write.seq, write.replica);
+            }
+            Operation::Remove { write: None, .. } => {}
+            Operation::Merge(state) => map.merge(state),
+        }
+    }
+
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::lww::map::{LWWMap, WriteId};
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::CRDT;
+
+    use super::{replay, Operation, OperationRecorder};
+
+    #[test]
+    fn replay_rebuilds_identical_map() {
+        let mut recorder = OperationRecorder::with_replica(ReplicaId::new(1));
+        recorder.insert("local", 0xC0FFEE);
+        recorder.insert("local", 0xBAD);
+        recorder.insert("removed", 0xDEAD);
+        recorder.remove("removed");
+        recorder.remove("absent");
+
+        let mut remote = LWWMap::with_replica(ReplicaId::new(2));
+        remote.insert("remote", 0xF00D);
+        remote.insert("local", 0xCAFE);
+        remote.insert("local", 0xCAFE);
+        remote.insert("local", 0xCAFE);
+        recorder.merge(remote.take());
+        recorder.insert("remote", 0xBEEF);
+
+        let write = WriteId {
+            replica: ReplicaId::new(1),
+            seq: 2,
+        };
+        assert!(matches!(
+            recorder.operations()[1],
+            Operation::Insert { write: w, .. } if w == write
+        ));
+        assert!(matches!(
+            recorder.operations()[4],
+            Operation::Remove { write: None, .. }
+        ));
+
+        let (map, ops) = recorder.into_parts();
+        let replayed = replay(ops);
+
+        assert_eq!(replayed.state(), map.state());
+        assert_eq!(replayed.digest(), map.digest());
+        assert_eq!(replayed.get("local"), Some(&0xCAFE));
+    }
+
+    #[test]
+    fn replay_keeps_recorded_versions() {
+        let mut recorder = OperationRecorder::with_replica(ReplicaId::new(2));
+        recorder.insert("key", 0xBAD);
+
+        let mut remote = LWWMap::with_replica(ReplicaId::new(1));
+        remote.insert("key", 0xC0FFEE);
+        remote.insert("key", 0xC0FFEE);
+        recorder.merge(remote.take());
+        recorder.insert("key", 0xF00D);
+        recorder.remove("key");
+
+        let (map, ops) = recorder.into_parts();
+        let replayed = replay(ops);
+
+        let reg = replayed.register("key").unwrap();
+        assert_eq!((reg.seq(), reg.replica()), (4, ReplicaId::new(2)));
+        assert_eq!(replayed.state(), map.state());
+    }
+}
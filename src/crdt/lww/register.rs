@@ -1,56 +1,336 @@
 //! This module defines an implementation of a CRDT that uses a Last-Write-Wins strategy to merge
 //! states together
 
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::Hash;
+
+use crate::crdt::hash::fnv1a;
+use crate::crdt::replica::ReplicaId;
 use crate::crdt::CRDT;
 
+/// Error returned when trying to update a register with a sequence number that is not greater
+/// than its current one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonMonotonicSeq {
+    /// The current sequence number of the register
+    pub current: u64,
+
+    /// The sequence number that has been rejected
+    pub requested: u64,
+}
+
+impl fmt::Display for NonMonotonicSeq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sequence number {} is not greater than current sequence number {}",
+            self.requested, self.current
+        )
+    }
+}
+
+impl std::error::Error for NonMonotonicSeq {}
+
+/// Error returned when a local write can not be recorded because the sequence number of the
+/// register reached [`u64::MAX`], e.g after merging the state of a misbehaving peer.
+///
+/// The sequence number does not saturate: two replicas that both wrote at [`u64::MAX`] would hold
+/// different values with the same version and never converge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeqExhausted;
+
+impl fmt::Display for SeqExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sequence number reached its highest value, no write can be recorded")
+    }
+}
+
+impl std::error::Error for SeqExhausted {}
+
+/// Error returned when building a register state from parts that would break its invariants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidState {
+    /// The state does not hold any value
+    MissingValue,
+
+    /// The sequence number of the state is zero, sequence numbers start at one
+    ZeroSeq,
+}
+
+impl fmt::Display for InvalidState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingValue => f.write_str("register state does not hold any value"),
+            Self::ZeroSeq => f.write_str("register state has a sequence number of zero"),
+        }
+    }
+}
+
+impl std::error::Error for InvalidState {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LWWState<T> {
-    value: Option<T>,
+    value: T,
 
     seq: u64,
+
+    /// The replica that wrote the value, used to break ties between values written concurrently
+    /// with the same sequence number
+    replica: ReplicaId,
+}
+
+/// States are ordered by their sequence number.
+/// States written concurrently, with the same sequence number by different replicas, are not
+/// comparable even though merging them picks the one written by the highest replica id
+impl<T: PartialEq> PartialOrd for LWWState<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.seq.cmp(&other.seq) {
+            Ordering::Equal => (self == other).then_some(Ordering::Equal),
+            ord => Some(ord),
+        }
+    }
 }
 
 impl<T> LWWState<T> {
-    fn update(&mut self, value: T) -> T {
-        let old = self.value.take().expect("register *always* holds a value");
-        self.value = Some(value);
-        self.seq += 1;
-        old
+    /// Build a state from its raw parts, e.g coming from an untrusted peer or storage.
+    /// The parts are validated so that the state always holds a value and has a sequence number
+    /// of at least one
+    pub fn from_parts(
+        value: Option<T>,
+        seq: u64,
+        replica: ReplicaId,
+    ) -> Result<Self, InvalidState> {
+        let value = value.ok_or(InvalidState::MissingValue)?;
+
+        if seq == 0 {
+            return Err(InvalidState::ZeroSeq);
+        }
+
+        Ok(Self {
+            value,
+            seq,
+            replica,
+        })
+    }
+
+    /// Decompose the state into its value, its sequence number and the replica that wrote it
+    pub fn into_parts(self) -> (T, u64, ReplicaId) {
+        (self.value, self.seq, self.replica)
+    }
+
+    /// Returns a reference to the value of this state
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Returns the sequence number of this state
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns the replica that wrote the value of this state
+    pub fn replica(&self) -> ReplicaId {
+        self.replica
+    }
+
+    /// Returns `true` if this state wins over `other` when they are merged together.
+    /// The state with the highest sequence number wins, ties are broken by the highest replica id
+    fn wins_over(&self, other: &Self) -> bool {
+        (self.seq, self.replica) > (other.seq, other.replica)
+    }
+
+    /// Returns the sequence number of the next local write
+    fn next_seq(&self) -> Result<u64, SeqExhausted> {
+        self.seq.checked_add(1).ok_or(SeqExhausted)
+    }
+
+    fn update(&mut self, value: T, replica: ReplicaId) -> Result<T, SeqExhausted> {
+        self.seq = self.next_seq()?;
+        self.replica = replica;
+        Ok(std::mem::replace(&mut self.value, value))
+    }
+
+    fn update_with_seq(
+        &mut self,
+        value: T,
+        seq: u64,
+        replica: ReplicaId,
+    ) -> Result<T, NonMonotonicSeq> {
+        if seq <= self.seq {
+            return Err(NonMonotonicSeq {
+                current: self.seq,
+                requested: seq,
+            });
+        }
+
+        let old = std::mem::replace(&mut self.value, value);
+        self.seq = seq;
+        self.replica = replica;
+        Ok(old)
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LWWRegister<T> {
     state: LWWState<T>,
 }
 
 impl<T> LWWRegister<T> {
-    /// Creates a new register that holds `value`
+    /// Creates a new register that holds `value`, written by an anonymous replica.
+    /// See [`Self::with_replica`] to break ties between concurrent writes deterministically
     pub fn new(value: T) -> Self {
+        Self::with_replica(value, ReplicaId::default())
+    }
+
+    /// Creates a new register that holds `value`, written by `replica`
+    pub fn with_replica(value: T, replica: ReplicaId) -> Self {
         Self {
             state: LWWState {
-                value: Some(value),
+                value,
                 seq: 1,
+                replica,
             },
         }
     }
 
+    /// Creates a register from the state of another register, keeping its sequence number
+    pub fn from_state(state: LWWState<T>) -> Self {
+        Self { state }
+    }
+
     /// Returns a reference to the current version of the value that this register holds
     pub fn value(&self) -> &T {
-        self.state
-            .value
-            .as_ref()
-            .expect("register *always* holds a value")
+        &self.state.value
+    }
+
+    /// Returns the sequence number of the current version of the value
+    pub fn seq(&self) -> u64 {
+        self.state.seq
     }
 
-    /// Update the current value with a new value and return the previous value
+    /// Returns the replica that wrote the current version of the value
+    pub fn replica(&self) -> ReplicaId {
+        self.state.replica
+    }
+
+    /// Update the current value with a new value and return the previous value.
+    /// The new value is written by an anonymous replica, see [`Self::update_by`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sequence number reached [`u64::MAX`], see [`Self::try_update`]
     pub fn update(&mut self, value: T) -> T {
-        self.state.update(value)
+        self.update_by(value, ReplicaId::default())
+    }
+
+    /// Update the current value with a new value written by `replica` and return the previous value
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sequence number reached [`u64::MAX`], see [`Self::try_update_by`]
+    pub fn update_by(&mut self, value: T, replica: ReplicaId) -> T {
+        match self.try_update_by(value, replica) {
+            Ok(old) => old,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Update the current value with a new value, see [`Self::update`].
+    /// Returns an error and leaves the register untouched if the sequence number reached
+    /// [`u64::MAX`], which a misbehaving peer can make a register reach through a merge
+    pub fn try_update(&mut self, value: T) -> Result<T, SeqExhausted> {
+        self.try_update_by(value, ReplicaId::default())
+    }
+
+    /// Update the current value with a new value written by `replica`, see [`Self::update_by`].
+    /// Returns an error and leaves the register untouched if the sequence number reached
+    /// [`u64::MAX`]
+    pub fn try_update_by(&mut self, value: T, replica: ReplicaId) -> Result<T, SeqExhausted> {
+        self.state.update(value, replica)
+    }
+
+    /// Update the current value with a new value written at an explicit sequence number and return
+    /// the previous value.
+    /// This lets the caller manage the sequence numbers externally, e.g to reserve ranges of
+    /// sequence numbers per source. `seq` must be strictly greater than the current sequence number
+    pub fn update_with_seq(&mut self, value: T, seq: u64) -> Result<T, NonMonotonicSeq> {
+        self.update_with_seq_by(value, seq, ReplicaId::default())
     }
 
-    /// Take the current value of the register
-    // TODO(oktal): I don't think this function should return an [`Option`] as it should be an
-    // invariant of the type that the state is *NEVER* [`None`]
-    pub(crate) fn take(mut self) -> Option<T> {
-        self.state.value.take()
+    /// Update the current value with a new value written by `replica` at an explicit sequence
+    /// number and return the previous value, see [`Self::update_with_seq`]
+    pub fn update_with_seq_by(
+        &mut self,
+        value: T,
+        seq: u64,
+        replica: ReplicaId,
+    ) -> Result<T, NonMonotonicSeq> {
+        self.state.update_with_seq(value, seq, replica)
+    }
+
+    /// Replace the current value with `value` and return the previous value, *without* recording a
+    /// new write: the sequence number and the replica are left unchanged, unlike [`Self::update`].
+    ///
+    /// This is meant for changes that are not logical writes, such as migrating a value to a new
+    /// format in place. The new value is not propagated by merges: a replica that holds the same
+    /// version keeps its own value, and a newer version from another replica overwrites it
+    pub fn replace_value(&mut self, value: T) -> T {
+        std::mem::replace(&mut self.state.value, value)
+    }
+
+    /// Returns a mutable reference to the current value, without recording a new write.
+    /// Callers must record the write themselves, see [`Self::update_with`]
+    pub(crate) fn value_mut(&mut self) -> &mut T {
+        &mut self.state.value
+    }
+
+    /// Update the current value in place, as a new write by `replica`.
+    /// Returns an error and does not call `f` if the sequence number reached [`u64::MAX`]
+    pub(crate) fn update_with(
+        &mut self,
+        replica: ReplicaId,
+        f: impl FnOnce(&mut T),
+    ) -> Result<(), SeqExhausted> {
+        self.state.seq = self.state.next_seq()?;
+        self.state.replica = replica;
+        f(&mut self.state.value);
+        Ok(())
+    }
+
+    /// Returns `true` if the current version of this register would win over `other` when merged,
+    /// that is if it has a higher sequence number, or the same sequence number and a higher replica
+    /// id
+    pub fn is_newer_than(&self, other: &LWWState<T>) -> bool {
+        self.state.wins_over(other)
+    }
+
+    /// Returns `true` if the current version of this register and `other` have been written
+    /// concurrently, with the same sequence number, by different replicas
+    pub fn is_concurrent_with(&self, other: &LWWState<T>) -> bool {
+        self.state.seq == other.seq && self.state.replica != other.replica
+    }
+}
+
+impl<T> LWWRegister<T>
+where
+    T: Hash,
+{
+    /// Merge the state of another register, breaking ties between concurrent values written with
+    /// the same sequence number by keeping the value with the largest hash.
+    /// Unlike [`CRDT::merge`], the outcome does not depend on which side the state is merged into.
+    /// Values are hashed with FNV-1a, whose output does not depend on the toolchain or the platform,
+    /// but `T` must hash identically on every replica
+    pub fn merge_by_hash(&mut self, other: LWWState<T>) {
+        if self.state.seq == other.seq {
+            if fnv1a(&other.value) > fnv1a(&self.state.value) {
+                self.state = other;
+            }
+
+            return;
+        }
+
+        self.merge(other)
     }
 }
 
@@ -63,12 +343,17 @@ impl<T> From<T> for LWWRegister<T> {
 impl<T> CRDT for LWWRegister<T> {
     type State = LWWState<T>;
 
+    /// Keep the state with the highest sequence number.
+    /// Concurrent values written with the same sequence number are resolved by keeping the value
+    /// written by the replica with the highest id.
+    /// Merging never panics, malformed states are rejected when built, see
+    /// [`LWWState::from_parts`]
     fn merge(&mut self, other: Self::State) {
-        if self.state.seq >= other.seq {
+        if !other.wins_over(&self.state) {
             return;
         }
 
-        self.state.value = other.value;
+        self.state = other;
     }
 
     fn take(self) -> Self::State {
@@ -76,11 +361,37 @@ impl<T> CRDT for LWWRegister<T> {
     }
 }
 
+/// Exchange the values of two registers, as a write by `replica`.
+///
+/// The swap is a write to both registers: the sequence number of each register is bumped and
+/// `replica` is recorded as their writer, so that merging them into other replicas propagates the
+/// swapped values, and concurrent writes with the same sequence number are resolved against
+/// `replica`.
+/// The two writes are independent from each other once they have been propagated: a concurrent
+/// write to either register that reaches a higher sequence number still wins over the swapped
+/// value of that register, while the other register keeps its swapped value.
+///
+/// Returns an error and leaves both registers untouched if the sequence number of either register
+/// reached [`u64::MAX`]
+pub fn swap<T>(
+    a: &mut LWWRegister<T>,
+    b: &mut LWWRegister<T>,
+    replica: ReplicaId,
+) -> Result<(), SeqExhausted> {
+    let seqs = (a.state.next_seq()?, b.state.next_seq()?);
+
+    std::mem::swap(&mut a.state.value, &mut b.state.value);
+    (a.state.seq, b.state.seq) = seqs;
+    (a.state.replica, b.state.replica) = (replica, replica);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::crdt::CRDTExt;
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::{CRDTExt, CRDT};
 
-    use super::LWWRegister;
+    use super::{InvalidState, LWWRegister, LWWState, NonMonotonicSeq, SeqExhausted};
 
     #[test]
     fn create_with_value() {
@@ -114,4 +425,265 @@ mod tests {
         // Recent should not have been overwitten as it's the most recent value
         assert_eq!(*recent.value(), 0xF00D);
     }
+
+    #[test]
+    fn merge_takes_the_seq_of_the_last() {
+        let mut recent = LWWRegister::new(0xC0FFEE);
+        let mut oldest = LWWRegister::new(0xBAD);
+
+        recent.update(0xF00D);
+        recent.update(0xCAFE);
+        recent.merge_into(&mut oldest);
+
+        assert_eq!(*oldest.value(), 0xCAFE);
+        assert_eq!(oldest.seq(), 3);
+    }
+
+    #[test]
+    fn update_with_increasing_seq() {
+        let mut reg = LWWRegister::new(0xC0FFEE);
+
+        assert_eq!(reg.update_with_seq(0xBAD, 10), Ok(0xC0FFEE));
+        assert_eq!(reg.update_with_seq(0xF00D, 20), Ok(0xBAD));
+
+        assert_eq!(*reg.value(), 0xF00D);
+        assert_eq!(reg.seq(), 20);
+    }
+
+    #[test]
+    fn update_with_decreasing_seq_is_rejected() {
+        let mut reg = LWWRegister::new(0xC0FFEE);
+        reg.update_with_seq(0xBAD, 10).unwrap();
+
+        let err = reg.update_with_seq(0xF00D, 5).unwrap_err();
+        assert_eq!(
+            err,
+            NonMonotonicSeq {
+                current: 10,
+                requested: 5
+            }
+        );
+        assert!(reg.update_with_seq(0xF00D, 10).is_err());
+
+        assert_eq!(*reg.value(), 0xBAD);
+        assert_eq!(reg.seq(), 10);
+    }
+
+    #[test]
+    fn merge_by_hash_converges_on_ties() {
+        let mut a = LWWRegister::new(0xC0FFEE);
+        let b = LWWRegister::new(0xBAD);
+        a.merge_by_hash(CRDT::take(b));
+
+        let a2 = LWWRegister::new(0xC0FFEE);
+        let mut b2 = LWWRegister::new(0xBAD);
+        b2.merge_by_hash(CRDT::take(a2));
+
+        assert_eq!(*a.value(), *b2.value());
+
+        // The hash has a fixed specification, the winner must not change with the toolchain
+        assert_eq!(*a.value(), 0xBAD);
+    }
+
+    #[test]
+    fn merge_by_hash_keeps_the_last() {
+        let mut recent = LWWRegister::new(0xC0FFEE);
+        recent.update(0xF00D);
+
+        let oldest = LWWRegister::new(0xBAD);
+        recent.merge_by_hash(CRDT::take(oldest));
+
+        assert_eq!(*recent.value(), 0xF00D);
+    }
+
+    #[test]
+    fn from_parts_rejects_malformed_states() {
+        assert_eq!(
+            LWWState::<u32>::from_parts(None, 1, ReplicaId::new(1)),
+            Err(InvalidState::MissingValue)
+        );
+        assert_eq!(
+            LWWState::from_parts(Some(0xBAD), 0, ReplicaId::new(1)),
+            Err(InvalidState::ZeroSeq)
+        );
+    }
+
+    #[test]
+    fn from_parts_round_trips() {
+        let state = LWWState::from_parts(Some(0xC0FFEE), 42, ReplicaId::new(1)).unwrap();
+        let mut reg = LWWRegister::new(0xBAD);
+        reg.merge(state);
+
+        assert_eq!(*reg.value(), 0xC0FFEE);
+        assert_eq!(
+            CRDT::take(reg).into_parts(),
+            (0xC0FFEE, 42, ReplicaId::new(1))
+        );
+    }
+
+    #[test]
+    fn compact_layout() {
+        use std::mem::size_of;
+
+        use crate::crdt::lww::map::Entry;
+
+        // A register is exactly its value, its sequence number and the replica that wrote it
+        assert_eq!(size_of::<LWWRegister<u64>>(), 3 * size_of::<u64>());
+        assert_eq!(size_of::<LWWRegister<Entry<u32>>>(), 3 * size_of::<u64>());
+        assert_eq!(
+            size_of::<LWWRegister<Entry<u64>>>(),
+            size_of::<Entry<u64>>() + 2 * size_of::<u64>()
+        );
+    }
+
+    #[test]
+    fn merge_breaks_ties_by_replica() {
+        let mut a = LWWRegister::with_replica(0xC0FFEE, ReplicaId::new(1));
+        let mut b = LWWRegister::with_replica(0xBAD, ReplicaId::new(2));
+
+        let (sa, sb) = (CRDT::take(a.clone()), CRDT::take(b.clone()));
+        a.merge(sb);
+        b.merge(sa);
+
+        assert_eq!(*a.value(), 0xBAD);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn swap_propagates_to_other_replicas() {
+        let mut a = LWWRegister::with_replica(0xC0FFEE, ReplicaId::new(1));
+        let mut b = LWWRegister::with_replica(0xBAD, ReplicaId::new(2));
+        let (mut remote_a, mut remote_b) = (a.clone(), b.clone());
+
+        super::swap(&mut a, &mut b, ReplicaId::new(3)).unwrap();
+        assert_eq!((*a.value(), *b.value()), (0xBAD, 0xC0FFEE));
+        assert_eq!(
+            (a.replica(), b.replica()),
+            (ReplicaId::new(3), ReplicaId::new(3))
+        );
+
+        // The swap is credited to the swapping replica, and wins a concurrent write with the same
+        // seq made by a lower replica
+        let mut concurrent = LWWRegister::with_replica(0xDEAD, ReplicaId::new(2));
+        concurrent.update_by(0xDEAD, ReplicaId::new(2));
+        let mut swapped = b.clone();
+        swapped.merge(CRDT::take(concurrent));
+        assert_eq!(swapped, b);
+
+        remote_a.merge(CRDT::take(a.clone()));
+        remote_b.merge(CRDT::take(b.clone()));
+        assert_eq!(remote_a, a);
+        assert_eq!(remote_b, b);
+
+        // A concurrent write that reaches a higher seq wins over the swapped value
+        let mut concurrent = LWWRegister::new(0xF00D);
+        concurrent.update(0xF00D);
+        concurrent.update(0xF00D);
+        concurrent.merge_into(&mut remote_a);
+        assert_eq!(*remote_a.value(), 0xF00D);
+    }
+
+    #[test]
+    fn compare_versions() {
+        let mut reg = LWWRegister::with_replica(0xC0FFEE, ReplicaId::new(1));
+        reg.update_by(0xC0FFEE, ReplicaId::new(1));
+
+        let older = CRDT::take(LWWRegister::with_replica(0xBAD, ReplicaId::new(2)));
+        assert!(reg.is_newer_than(&older));
+        assert!(!reg.is_concurrent_with(&older));
+
+        let mut newer = LWWRegister::with_replica(0xF00D, ReplicaId::new(1));
+        newer.update(0xF00D);
+        newer.update(0xF00D);
+        let newer = CRDT::take(newer);
+        assert!(!reg.is_newer_than(&newer));
+        assert!(!reg.is_concurrent_with(&newer));
+
+        let concurrent = LWWState::from_parts(Some(0xCAFE), 2, ReplicaId::new(2)).unwrap();
+        assert!(!reg.is_newer_than(&concurrent));
+        assert!(reg.is_concurrent_with(&concurrent));
+
+        let concurrent = LWWState::from_parts(Some(0xCAFE), 2, ReplicaId::default()).unwrap();
+        assert!(reg.is_newer_than(&concurrent));
+        assert!(reg.is_concurrent_with(&concurrent));
+
+        let same = CRDT::take(reg.clone());
+        assert!(!reg.is_newer_than(&same));
+        assert!(!reg.is_concurrent_with(&same));
+    }
+
+    #[test]
+    fn states_are_partially_ordered() {
+        let state = |seq, replica| {
+            LWWState::from_parts(Some(0xC0FFEE), seq, ReplicaId::new(replica)).unwrap()
+        };
+
+        assert!(state(2, 1) > state(1, 1));
+        assert!(state(1, 2) < state(2, 1));
+        assert!(state(3, 1) >= state(3, 1));
+
+        // Concurrent states
+        assert_eq!(state(2, 1).partial_cmp(&state(2, 2)), None);
+
+        let other = LWWState::from_parts(Some(0xBAD), 2, ReplicaId::new(1)).unwrap();
+        assert_eq!(state(2, 1).partial_cmp(&other), None);
+    }
+
+    #[test]
+    fn writes_fail_after_merging_max_seq() {
+        let hostile = LWWState::from_parts(Some(0xBAD), u64::MAX, ReplicaId::new(1)).unwrap();
+
+        let mut a = LWWRegister::with_replica(0xC0FFEE, ReplicaId::new(2));
+        let mut b = LWWRegister::with_replica(0xC0FFEE, ReplicaId::new(3));
+        a.merge(hostile.clone());
+        b.merge(hostile);
+        assert_eq!(*a.value(), 0xBAD);
+
+        // Writes can not be recorded, rather than leaving two values with the same version
+        assert_eq!(a.try_update_by(10, ReplicaId::new(2)), Err(SeqExhausted));
+        assert_eq!(b.try_update_by(20, ReplicaId::new(3)), Err(SeqExhausted));
+        assert_eq!(a, b);
+
+        let mut fresh = LWWRegister::new(0xF00D);
+        assert_eq!(
+            super::swap(&mut a, &mut fresh, ReplicaId::new(2)),
+            Err(SeqExhausted)
+        );
+        assert_eq!((*a.value(), *fresh.value()), (0xBAD, 0xF00D));
+        assert_eq!(fresh.seq(), 1);
+    }
+
+    #[test]
+    fn replace_value_keeps_seq() {
+        let mut reg = LWWRegister::with_replica(0xC0FFEE, ReplicaId::new(1));
+        reg.update_by(0xBAD, ReplicaId::new(2));
+
+        assert_eq!(reg.replace_value(0xF00D), 0xBAD);
+        assert_eq!(*reg.value(), 0xF00D);
+        assert_eq!((reg.seq(), reg.replica()), (2, ReplicaId::new(2)));
+
+        reg.update(0xDEAD);
+        assert_eq!(reg.seq(), 3);
+    }
+
+    #[test]
+    fn priority_breaks_ties_first() {
+        let primary = ReplicaId::with_priority(1, 1);
+        let secondary = ReplicaId::with_priority(9, 0);
+        assert_eq!((primary.priority(), secondary.priority()), (1, 0));
+
+        let mut reg = LWWRegister::with_replica(0xBAD, secondary);
+        reg.merge(CRDT::take(LWWRegister::with_replica(0xC0FFEE, primary)));
+        assert_eq!(*reg.value(), 0xC0FFEE);
+
+        let mut reg = LWWRegister::with_replica(0xC0FFEE, primary);
+        reg.merge(CRDT::take(LWWRegister::with_replica(0xBAD, secondary)));
+        assert_eq!(*reg.value(), 0xC0FFEE);
+
+        // A higher sequence number still wins over a higher priority
+        let mut newer = LWWRegister::with_replica(0xBAD, secondary);
+        newer.update_by(0xF00D, secondary);
+        reg.merge(CRDT::take(newer));
+        assert_eq!(*reg.value(), 0xF00D);
+    }
 }
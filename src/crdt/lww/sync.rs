@@ -0,0 +1,213 @@
+//! Module that defines a thread-safe wrapper around [`LWWMap`]
+
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::sync::RwLock;
+
+use crate::crdt::CRDT;
+
+use super::map::{LWWMap, MapState};
+
+/// A [`LWWMap`] that can be shared between threads, typically behind an [`std::sync::Arc`]
+pub struct SyncLWWMap<K, V> {
+    inner: RwLock<LWWMap<K, V>>,
+}
+
+impl<K, V> SyncLWWMap<K, V> {
+    /// Create a new, empty map
+    pub fn new() -> Self {
+        Self::from(LWWMap::new())
+    }
+
+    /// Consume the wrapper and return the underlying map
+    pub fn into_inner(self) -> LWWMap<K, V> {
+        self.inner.into_inner().expect("lock poisoned")
+    }
+}
+
+impl<K, V> Default for SyncLWWMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> From<LWWMap<K, V>> for SyncLWWMap<K, V> {
+    fn from(map: LWWMap<K, V>) -> Self {
+        Self {
+            inner: RwLock::new(map),
+        }
+    }
+}
+
+impl<K, V> SyncLWWMap<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Returns a copy of the value corresponding to the key.
+    pub fn get<Q>(&self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.inner.read().expect("lock poisoned").get(k).cloned()
+    }
+
+    /// Inserts a key-value pair into the map.
+    /// See [`LWWMap::insert`]
+    pub fn insert(&self, k: K, v: V) -> Option<V> {
+        self.inner.write().expect("lock poisoned").insert(k, v)
+    }
+
+    /// Removes a key from the map.
+    /// See [`LWWMap::remove`]
+    pub fn remove<Q>(&self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.write().expect("lock poisoned").remove(k)
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.read().expect("lock poisoned").contains_key(k)
+    }
+
+    /// Merge the state of another replica into this map
+    pub fn merge(&self, other: MapState<K, V>) {
+        self.inner.write().expect("lock poisoned").merge(other)
+    }
+
+    /// Returns a copy of the state of this map, to be merged into another replica
+    pub fn state(&self) -> MapState<K, V>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.inner.read().expect("lock poisoned").state().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::crdt::lww::map::{LWWMap, MapState};
+    use crate::crdt::lww::register::{LWWRegister, LWWState};
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::testing::XorShift;
+    use crate::crdt::CRDT;
+
+    use super::SyncLWWMap;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn types_are_send_and_sync() {
+        assert_send_sync::<LWWRegister<String>>();
+        assert_send_sync::<LWWState<String>>();
+        assert_send_sync::<LWWMap<String, Vec<u8>>>();
+        assert_send_sync::<MapState<String, Vec<u8>>>();
+        assert_send_sync::<SyncLWWMap<String, Vec<u8>>>();
+    }
+
+    #[test]
+    fn concurrent_writers_converge() {
+        const THREADS: u64 = 8;
+        const KEYS: u64 = 64;
+
+        let map = Arc::new(SyncLWWMap::new());
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let map = Arc::clone(&map);
+                thread::spawn(move || {
+                    // Every thread writes its own keys and merges the keys of a remote replica
+                    let mut remote = LWWMap::new();
+                    for k in 0..KEYS {
+                        map.insert((t, k), k);
+                        remote.insert((t + THREADS, k), k);
+                    }
+
+                    map.merge(remote.take());
+
+                    for k in (0..KEYS).step_by(2) {
+                        map.remove(&(t, k));
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().expect("writer thread panicked");
+        }
+
+        for t in 0..THREADS {
+            for k in 0..KEYS {
+                let expected = (k % 2 == 1).then_some(k);
+                assert_eq!(map.get(&(t, k)), expected);
+                assert_eq!(map.get(&(t + THREADS, k)), Some(k));
+            }
+        }
+    }
+
+    #[test]
+    fn contended_replicas_converge() {
+        const REPLICAS: u64 = 4;
+        const KEYS: u64 = 8;
+        const OPS: usize = 500;
+
+        let replicas: Vec<_> = (1..=REPLICAS)
+            .map(|id| Arc::new(SyncLWWMap::from(LWWMap::with_replica(ReplicaId::new(id)))))
+            .collect();
+
+        // Every replica is written by two threads, and every thread writes the same few keys and
+        // regularly merges the state of the next replica
+        let handles: Vec<_> = (0..2 * REPLICAS)
+            .map(|t| {
+                let map = Arc::clone(&replicas[(t % REPLICAS) as usize]);
+                let next = Arc::clone(&replicas[((t + 1) % REPLICAS) as usize]);
+                thread::spawn(move || {
+                    let mut rng = XorShift::new(0xC0FFEE + t);
+                    for op in 0..OPS {
+                        let k = rng.next_u64() % KEYS;
+                        match rng.next_u64() % 4 {
+                            0 => {
+                                map.remove(&k);
+                            }
+                            _ => {
+                                map.insert(k, rng.next_u64());
+                            }
+                        }
+
+                        if op % 16 == 0 {
+                            map.merge(next.state());
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().expect("writer thread panicked");
+        }
+
+        let states: Vec<_> = replicas.iter().map(|r| r.state()).collect();
+        for replica in &replicas {
+            for state in &states {
+                replica.merge(state.clone());
+            }
+        }
+
+        let first = replicas[0].state();
+        for replica in &replicas[1..] {
+            assert_eq!(replica.state(), first);
+        }
+    }
+}
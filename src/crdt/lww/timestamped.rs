@@ -0,0 +1,152 @@
+//! Module that defines a Last-Write-Wins value stamped with the system clock
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::crdt::replica::ReplicaId;
+use crate::crdt::CRDT;
+
+use super::register::{LWWRegister, LWWState, SeqExhausted};
+
+/// Returns the number of nanoseconds elapsed since the Unix epoch, or zero if the system clock is
+/// set before the epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX))
+        .unwrap_or(0)
+}
+
+/// A [`LWWRegister`] whose sequence numbers are timestamps taken from the system clock, so that
+/// the last value that has been set wins regardless of how many times each replica set a value.
+///
+/// The clocks of the replicas are trusted: a replica whose clock is ahead wins over concurrent
+/// writes of the other replicas. Sequence numbers are kept strictly increasing locally even if the
+/// system clock goes backward.
+/// Values set in the same nanosecond are resolved by the id of the replica that set them, see
+/// [`Self::with_replica`]
+#[derive(Debug, Clone)]
+pub struct Lww<T> {
+    reg: LWWRegister<T>,
+
+    /// The replica that sets the local values
+    replica: ReplicaId,
+}
+
+impl<T> Lww<T> {
+    /// Create a new value, stamped with the current time and set by an anonymous replica.
+    /// Two anonymous replicas that set different values in the same nanosecond do not converge,
+    /// see [`Self::with_replica`]
+    pub fn new(value: T) -> Self {
+        Self::with_replica(value, ReplicaId::default())
+    }
+
+    /// Create a new value, stamped with the current time, whose local values are set by `replica`
+    pub fn with_replica(value: T, replica: ReplicaId) -> Self {
+        let state = LWWState::from_parts(Some(value), now().max(1), replica)
+            .expect("state has a value and a non-zero seq");
+
+        Self {
+            reg: LWWRegister::from_state(state),
+            replica,
+        }
+    }
+
+    /// Set a new value, stamped with the current time, and return the previous value.
+    /// Returns an error and leaves the value untouched if the sequence number reached [`u64::MAX`],
+    /// e.g after merging the state of a misbehaving peer
+    pub fn set(&mut self, value: T) -> Result<T, SeqExhausted> {
+        self.set_at(value, now())
+    }
+
+    /// Set a new value stamped with `ts`, or with the next sequence number if `ts` is not more
+    /// recent than the current value
+    fn set_at(&mut self, value: T, ts: u64) -> Result<T, SeqExhausted> {
+        let seq = ts.max(self.reg.seq().checked_add(1).ok_or(SeqExhausted)?);
+        Ok(self
+            .reg
+            .update_with_seq_by(value, seq, self.replica)
+            .expect("seq is greater than the current seq"))
+    }
+
+    /// Returns a reference to the current value
+    pub fn get(&self) -> &T {
+        self.reg.value()
+    }
+}
+
+/// Values are equal if they hold the same write, whatever replica owns them
+impl<T: PartialEq> PartialEq for Lww<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.reg == other.reg
+    }
+}
+
+impl<T: Eq> Eq for Lww<T> {}
+
+impl<T> CRDT for Lww<T> {
+    type State = LWWState<T>;
+
+    fn merge(&mut self, other: Self::State) {
+        self.reg.merge(other)
+    }
+
+    fn take(self) -> Self::State {
+        CRDT::take(self.reg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::lww::register::{LWWState, SeqExhausted};
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::{CRDTExt, CRDT};
+
+    use super::Lww;
+
+    #[test]
+    fn later_set_wins() {
+        let mut a = Lww::with_replica(0, ReplicaId::new(1));
+        let mut b = Lww::with_replica(0xBAD, ReplicaId::new(2));
+        // A timestamp after the creation of both values
+        let ts = super::now() + 1_000_000_000;
+
+        // Many sets with the same timestamp only bump the seq by one each
+        for i in 1..=100 {
+            a.set_at(i, ts).unwrap();
+        }
+        assert_eq!(a.reg.seq(), ts + 99);
+        b.set_at(0xC0FFEE, ts + 1_000).unwrap();
+
+        a.clone().merge_into(&mut b);
+        b.clone().merge_into(&mut a);
+
+        assert_eq!(*a.get(), 0xC0FFEE);
+        assert_eq!(a.take(), b.take());
+    }
+
+    #[test]
+    fn same_timestamp_converges() {
+        let mut a = Lww::with_replica(0, ReplicaId::new(1));
+        let mut b = Lww::with_replica(0, ReplicaId::new(2));
+        // A timestamp after the creation of both values
+        let ts = super::now() + 1_000_000_000;
+        a.set_at(0xC0FFEE, ts).unwrap();
+        b.set_at(0xBAD, ts).unwrap();
+        assert_eq!(a.reg.seq(), b.reg.seq());
+
+        a.clone().merge_into(&mut b);
+        b.clone().merge_into(&mut a);
+
+        assert_eq!(a, b);
+        assert_eq!(*a.get(), 0xBAD);
+    }
+
+    #[test]
+    fn set_fails_after_merging_max_seq() {
+        let mut lww = Lww::new(0xC0FFEE);
+        lww.merge(LWWState::from_parts(Some(0xBAD), u64::MAX, ReplicaId::new(1)).unwrap());
+
+        assert_eq!(lww.set(0xF00D), Err(SeqExhausted));
+        assert_eq!(*lww.get(), 0xBAD);
+    }
+}
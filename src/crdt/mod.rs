@@ -1,9 +1,31 @@
+#[cfg(test)]
+#[macro_use]
+mod testing;
+
+pub mod aggregate;
+pub mod builder;
+pub mod counter;
+mod hash;
+pub mod log;
 pub mod lww;
+pub mod mv;
+pub mod presence;
+pub mod register;
+pub mod replica;
+pub mod stability;
+
+use std::fmt;
 
+#[allow(clippy::upper_case_acronyms)]
 pub trait CRDT {
     type State;
 
     fn merge(&mut self, other: Self::State);
+
+    /// Consume this replica and return its state.
+    /// The state must keep everything that is needed to converge, such as the seqs of removed
+    /// entries: a replica rebuilt from it must be equal to this one, and merging it back must not
+    /// change anything
     fn take(self) -> Self::State;
 }
 
@@ -14,6 +36,133 @@ pub trait CRDTExt: CRDT {
     {
         other.merge(self.take())
     }
+
+    /// Merge `other` only if the merged state satisfies `invariant`, otherwise return an error and
+    /// leave this replica untouched.
+    /// The merge is first performed on a clone of this replica to check the invariant, then
+    /// performed again on this replica.
+    ///
+    /// Rejecting a merge breaks strong eventual consistency: replicas that rejected different
+    /// states will not converge anymore. This is meant for advanced use, where an application
+    /// prefers diverging replicas over a state that violates its invariants
+    fn merge_checked(
+        &mut self,
+        other: Self::State,
+        invariant: impl Fn(&Self) -> bool,
+    ) -> Result<(), InvariantViolated>
+    where
+        Self: Clone + Sized,
+        Self::State: Clone,
+    {
+        let mut merged = self.clone();
+        merged.merge(other.clone());
+        if !invariant(&merged) {
+            return Err(InvariantViolated);
+        }
+
+        self.merge(other);
+        Ok(())
+    }
 }
 
 impl<C> CRDTExt for C where C: CRDT {}
+
+/// Error returned when a merge is rejected because its result violates an invariant, see
+/// [`CRDTExt::merge_checked`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvariantViolated;
+
+impl fmt::Display for InvariantViolated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("merge rejected, the merged state violates an invariant")
+    }
+}
+
+impl std::error::Error for InvariantViolated {}
+
+/// Merge every replica into the first one and return it, or [`None`] if there are no replicas
+pub fn converge<C: CRDT>(replicas: impl IntoIterator<Item = C>) -> Option<C> {
+    let mut replicas = replicas.into_iter();
+    let mut first = replicas.next()?;
+
+    for replica in replicas {
+        replica.merge_into(&mut first);
+    }
+
+    Some(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::counter::array::ArrayGCounter;
+    use super::lww::map::LWWMap;
+    use super::replica::ReplicaId;
+    use super::{converge, CRDTExt, InvariantViolated, CRDT};
+
+    #[test]
+    fn converge_nothing() {
+        assert!(converge(Vec::<LWWMap<u32, u32>>::new()).is_none());
+    }
+
+    #[test]
+    fn converge_regardless_of_order() {
+        let mut a = LWWMap::new();
+        a.insert("a", 0xA);
+        a.insert("shared", 0xBAD);
+
+        let mut b = LWWMap::new();
+        b.insert("b", 0xB);
+        b.insert("shared", 0xBAD);
+        b.insert("shared", 0xC0FFEE);
+
+        let mut c = LWWMap::new();
+        c.insert("c", 0xC);
+        c.insert("shared", 0xBAD);
+        c.insert("shared", 0xBAD);
+        c.insert("shared", 0xF00D);
+        c.insert("a", 0xDEAD);
+        c.remove("a");
+
+        let orders = [
+            [&a, &b, &c],
+            [&a, &c, &b],
+            [&b, &a, &c],
+            [&b, &c, &a],
+            [&c, &a, &b],
+            [&c, &b, &a],
+        ];
+
+        let converged: Vec<_> = orders
+            .into_iter()
+            .map(|replicas| converge(replicas.into_iter().cloned()).unwrap())
+            .collect();
+
+        for map in &converged {
+            assert_eq!(map, &converged[0]);
+        }
+
+        let map = &converged[0];
+        assert_eq!(map.get("shared"), Some(&0xF00D));
+        assert_eq!(map.get("b"), Some(&0xB));
+        assert_eq!(map.get("c"), Some(&0xC));
+        assert!(!map.contains_key("a"));
+    }
+
+    #[test]
+    fn merge_checked_rejects_violations() {
+        const CAP: u64 = 100;
+        let below_cap = |c: &ArrayGCounter<2>| c.value() <= CAP;
+
+        let mut a = ArrayGCounter::<2>::new();
+        a.increment(ReplicaId::new(0), 60).unwrap();
+
+        let mut b = ArrayGCounter::<2>::new();
+        b.increment(ReplicaId::new(1), 30).unwrap();
+        assert_eq!(a.merge_checked(b.take(), below_cap), Ok(()));
+        assert_eq!(a.value(), 90);
+
+        b.increment(ReplicaId::new(1), 20).unwrap();
+        assert_eq!(a.merge_checked(b.take(), below_cap), Err(InvariantViolated));
+        assert_eq!(a.value(), 90);
+    }
+}
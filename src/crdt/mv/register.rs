@@ -0,0 +1,240 @@
+//! This module defines an implementation of a Multi-Value register, a CRDT that keeps every
+//! concurrently written value instead of arbitrarily picking one of them
+
+use std::collections::BTreeMap;
+
+use crate::crdt::replica::{Dot, ReplicaId};
+use crate::crdt::CRDT;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MVState<T> {
+    /// The values that are currently concurrent, tagged by the write that produced them, sorted by
+    /// write so that states holding the same values are equal
+    values: Vec<(Dot, T)>,
+
+    /// Every write that this state has observed, per replica
+    clock: BTreeMap<ReplicaId, u64>,
+}
+
+impl<T> MVState<T> {
+    /// Returns `true` if the write identified by `dot` has been observed by this state
+    fn has_seen(&self, dot: &Dot) -> bool {
+        self.clock
+            .get(&dot.replica)
+            .map(|&counter| counter >= dot.counter)
+            .unwrap_or(false)
+    }
+
+    fn contains(&self, dot: &Dot) -> bool {
+        self.values.iter().any(|(d, _)| d == dot)
+    }
+
+    /// Deterministically collapse concurrent values down to `cap`, keeping the values that have
+    /// been written by the replicas with the highest ids.
+    /// Values that are dropped remain covered by the clock so they can never be resurrected by a
+    /// later merge
+    fn collapse(&mut self, cap: usize) {
+        if self.values.len() <= cap {
+            return;
+        }
+
+        self.values
+            .sort_by(|(a, _), (b, _)| b.replica.cmp(&a.replica).then(b.counter.cmp(&a.counter)));
+        self.values.truncate(cap);
+    }
+}
+
+/// A register that holds all the values that have been written concurrently
+#[derive(Debug, Clone)]
+pub struct MVRegister<T> {
+    replica: ReplicaId,
+
+    cap: Option<usize>,
+
+    state: MVState<T>,
+}
+
+impl<T> MVRegister<T> {
+    /// Creates a new, empty register owned by `replica`
+    pub fn new(replica: ReplicaId) -> Self {
+        Self {
+            replica,
+            cap: None,
+            state: MVState {
+                values: Vec::new(),
+                clock: BTreeMap::new(),
+            },
+        }
+    }
+
+    /// Creates a new, empty register owned by `replica` that holds at most `cap` concurrent values.
+    /// When more than `cap` values are concurrent, only the values written by the `cap` replicas
+    /// with the highest ids are kept
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cap` is zero
+    pub fn with_cap(replica: ReplicaId, cap: usize) -> Self {
+        assert!(
+            cap > 0,
+            "a register must be able to hold at least one value"
+        );
+
+        Self {
+            cap: Some(cap),
+            ..Self::new(replica)
+        }
+    }
+
+    /// Returns the maximum number of concurrent values this register can hold, if any
+    pub fn cap(&self) -> Option<usize> {
+        self.cap
+    }
+
+    /// Write a new value, overwriting all the values that this register currently holds
+    pub fn set(&mut self, value: T) {
+        let counter = self.state.clock.entry(self.replica).or_insert(0);
+        *counter += 1;
+
+        let dot = Dot {
+            replica: self.replica,
+            counter: *counter,
+        };
+
+        self.state.values.clear();
+        self.state.values.push((dot, value));
+    }
+
+    /// Returns an iterator over the values that are currently concurrent
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.state.values.iter().map(|(_, v)| v)
+    }
+
+    /// Returns the number of values that are currently concurrent
+    pub fn len(&self) -> usize {
+        self.state.values.len()
+    }
+
+    /// Returns `true` if no value has ever been written to this register
+    pub fn is_empty(&self) -> bool {
+        self.state.values.is_empty()
+    }
+}
+
+/// Registers are equal if they hold the same state, whatever replica owns them
+impl<T: PartialEq> PartialEq for MVRegister<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.state == other.state
+    }
+}
+
+impl<T: Eq> Eq for MVRegister<T> {}
+
+impl<T> CRDT for MVRegister<T> {
+    type State = MVState<T>;
+
+    fn merge(&mut self, other: Self::State) {
+        // Keep our values that the other side either holds as well or has never seen
+        let values = std::mem::take(&mut self.state.values);
+        self.state.values = values
+            .into_iter()
+            .filter(|(dot, _)| other.contains(dot) || !other.has_seen(dot))
+            .collect();
+
+        // Add the values of the other side that we have never seen
+        for (dot, value) in other.values {
+            if !self.state.contains(&dot) && !self.state.has_seen(&dot) {
+                self.state.values.push((dot, value));
+            }
+        }
+
+        for (replica, counter) in other.clock {
+            let c = self.state.clock.entry(replica).or_insert(0);
+            *c = (*c).max(counter);
+        }
+
+        if let Some(cap) = self.cap {
+            self.state.collapse(cap);
+        }
+
+        self.state.values.sort_unstable_by_key(|(dot, _)| *dot);
+    }
+
+    fn take(self) -> Self::State {
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::CRDT;
+
+    use super::MVRegister;
+
+    fn sorted<T: Clone + Ord>(reg: &MVRegister<T>) -> Vec<T> {
+        let mut values: Vec<_> = reg.values().cloned().collect();
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn set_overwrites_previous_values() {
+        let mut reg = MVRegister::new(ReplicaId::new(1));
+        reg.set(0xC0FFEE);
+        reg.set(0xBAD);
+
+        assert_eq!(sorted(&reg), vec![0xBAD]);
+    }
+
+    #[test]
+    fn merge_keeps_concurrent_values() {
+        let mut a = MVRegister::new(ReplicaId::new(1));
+        let mut b = MVRegister::new(ReplicaId::new(2));
+
+        a.set(0xC0FFEE);
+        b.set(0xBAD);
+
+        assert_converges!(a, b);
+
+        a.merge(b.clone().take());
+        assert_eq!(sorted(&a), vec![0xBAD, 0xC0FFEE]);
+
+        // A write that has seen both values overwrites them
+        a.set(0xF00D);
+        assert_converges!(a, b);
+        b.merge(a.clone().take());
+        assert_eq!(sorted(&b), vec![0xF00D]);
+    }
+
+    #[test]
+    fn cap_collapses_deterministically() {
+        const CAP: usize = 3;
+
+        let mut replicas: Vec<_> = (1..=8)
+            .map(|id| MVRegister::with_cap(ReplicaId::new(id), CAP))
+            .collect();
+
+        for (i, reg) in replicas.iter_mut().enumerate() {
+            reg.set(i as u64 + 1);
+        }
+
+        // Merge every replica into every other one, in a different order for each of them
+        let states: Vec<_> = replicas.iter().map(|reg| reg.clone().take()).collect();
+        for (i, reg) in replicas.iter_mut().enumerate() {
+            for j in (0..states.len()).rev().cycle().skip(i).take(states.len()) {
+                reg.merge(states[j].clone());
+            }
+        }
+
+        for reg in &replicas {
+            assert_eq!(reg.len(), CAP);
+            assert_eq!(sorted(reg), vec![6, 7, 8]);
+            assert_eq!(reg, &replicas[0]);
+        }
+
+        // Collapsed values must not come back through a replica that still holds them
+        replicas[1].merge(states[0].clone());
+        assert_eq!(sorted(&replicas[1]), vec![6, 7, 8]);
+    }
+}
@@ -0,0 +1,172 @@
+//! This module defines a CRDT that tracks the ephemeral presence of peers, such as the position of
+//! their cursor in a collaborative editor
+
+use std::collections::BTreeMap;
+
+use crate::crdt::CRDT;
+
+/// The presence state of a single peer along with the timestamp of its last heartbeat
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Heartbeat<S> {
+    state: S,
+
+    ts: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresenceState<Id, S> {
+    peers: BTreeMap<Id, Heartbeat<S>>,
+}
+
+/// A map of peers to their presence state, where a peer that did not send a heartbeat for longer
+/// than a time-to-live is considered absent.
+///
+/// Every peer is expected to only write its own presence, which makes its heartbeat timestamps
+/// unique. Timestamps and the time-to-live share the same, arbitrary, unit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Presence<Id, S> {
+    ttl: u64,
+
+    state: PresenceState<Id, S>,
+}
+
+impl<Id, S> Presence<Id, S> {
+    /// Create an empty presence map where peers expire `ttl` after their last heartbeat
+    pub fn new(ttl: u64) -> Self {
+        Self {
+            ttl,
+            state: PresenceState {
+                peers: BTreeMap::new(),
+            },
+        }
+    }
+
+    /// Returns the time-to-live of a heartbeat
+    pub fn ttl(&self) -> u64 {
+        self.ttl
+    }
+
+    fn is_active(&self, heartbeat: &Heartbeat<S>, now: u64) -> bool {
+        now.saturating_sub(heartbeat.ts) <= self.ttl
+    }
+}
+
+impl<Id, S> Presence<Id, S>
+where
+    Id: Ord,
+{
+    /// Record a heartbeat of `id` at time `ts` with its current presence state.
+    /// A heartbeat older than the last known heartbeat of `id` is ignored
+    pub fn heartbeat(&mut self, id: Id, state: S, ts: u64) {
+        self.merge_heartbeat(id, Heartbeat { state, ts });
+    }
+
+    /// Returns the presence state of `id` if it is present at time `now`
+    pub fn get(&self, id: &Id, now: u64) -> Option<&S> {
+        self.state
+            .peers
+            .get(id)
+            .filter(|heartbeat| self.is_active(heartbeat, now))
+            .map(|heartbeat| &heartbeat.state)
+    }
+
+    /// Returns an iterator over the peers that are present at time `now`, in arbitrary order
+    pub fn active(&self, now: u64) -> impl Iterator<Item = (&Id, &S)> {
+        self.state
+            .peers
+            .iter()
+            .filter(move |(_, heartbeat)| self.is_active(heartbeat, now))
+            .map(|(id, heartbeat)| (id, &heartbeat.state))
+    }
+
+    /// Forget the peers that are absent at time `now` and return how many have been forgotten
+    pub fn expire(&mut self, now: u64) -> usize {
+        let ttl = self.ttl;
+        let len = self.state.peers.len();
+        self.state
+            .peers
+            .retain(|_, heartbeat| now.saturating_sub(heartbeat.ts) <= ttl);
+        len - self.state.peers.len()
+    }
+
+    fn merge_heartbeat(&mut self, id: Id, heartbeat: Heartbeat<S>) {
+        match self.state.peers.get_mut(&id) {
+            Some(ours) if ours.ts >= heartbeat.ts => {}
+            Some(ours) => *ours = heartbeat,
+            None => {
+                self.state.peers.insert(id, heartbeat);
+            }
+        }
+    }
+}
+
+impl<Id, S> CRDT for Presence<Id, S>
+where
+    Id: Ord,
+{
+    type State = PresenceState<Id, S>;
+
+    /// Keep the latest heartbeat of every peer
+    fn merge(&mut self, other: Self::State) {
+        for (id, heartbeat) in other.peers {
+            self.merge_heartbeat(id, heartbeat);
+        }
+    }
+
+    fn take(self) -> Self::State {
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::{CRDTExt, CRDT};
+
+    use super::Presence;
+
+    const TTL: u64 = 30;
+
+    #[test]
+    fn peer_drops_out_after_ttl() {
+        let mut presence = Presence::new(TTL);
+        presence.heartbeat("alice", 0xC0FFEE, 100);
+        presence.heartbeat("bob", 0xBAD, 100);
+
+        // Only alice keeps sending heartbeats
+        presence.heartbeat("alice", 0xF00D, 120);
+        assert_eq!(presence.get(&"bob", 130), Some(&0xBAD));
+
+        let mut active: Vec<_> = presence.active(140).collect();
+        active.sort();
+        assert_eq!(active, vec![(&"alice", &0xF00D)]);
+        assert_eq!(presence.get(&"bob", 140), None);
+
+        assert_eq!(presence.expire(140), 1);
+        assert_eq!(presence.expire(140), 0);
+    }
+
+    #[test]
+    fn concurrent_updates_merge_to_latest() {
+        let mut a = Presence::new(TTL);
+        let mut b = Presence::new(TTL);
+
+        a.heartbeat("alice", 1, 100);
+        b.heartbeat("alice", 2, 110);
+        b.heartbeat("bob", 0xBAD, 105);
+        a.heartbeat("bob", 0xF00D, 115);
+
+        let (sa, sb) = (a.clone().take(), b.clone().take());
+        a.merge(sb);
+        b.merge(sa);
+
+        assert_eq!(a, b);
+        assert_eq!(a.get(&"alice", 120), Some(&2));
+        assert_eq!(a.get(&"bob", 120), Some(&0xF00D));
+
+        // A stale heartbeat does not move a peer back
+        let mut stale = Presence::new(TTL);
+        stale.heartbeat("alice", 0xDEAD, 90);
+        stale.merge_into(&mut a);
+        assert_eq!(a.get(&"alice", 120), Some(&2));
+    }
+}
@@ -0,0 +1,211 @@
+//! This module defines a boolean flag that can be enabled and disabled concurrently by multiple
+//! replicas.
+//! Every write is tagged, and a write only overrides the writes it has observed, which lets
+//! concurrent writes be detected and resolved according to a [`FlagPolicy`]
+
+use std::collections::BTreeMap;
+
+use crate::crdt::replica::{Dot, ReplicaId};
+use crate::crdt::CRDT;
+
+/// How concurrent enable and disable operations are resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlagPolicy {
+    /// The flag is enabled if any concurrent write enabled it
+    EnableWins,
+
+    /// The flag is disabled if any concurrent write disabled it
+    DisableWins,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagState {
+    /// The writes that have not been overridden yet, along with the value they wrote
+    writes: BTreeMap<Dot, bool>,
+
+    /// Every write that this state has observed, per replica
+    clock: BTreeMap<ReplicaId, u64>,
+}
+
+impl FlagState {
+    fn has_seen(&self, dot: &Dot) -> bool {
+        self.clock
+            .get(&dot.replica)
+            .map(|&counter| counter >= dot.counter)
+            .unwrap_or(false)
+    }
+}
+
+/// A convergent boolean flag, disabled until it is enabled for the first time
+#[derive(Debug, Clone)]
+pub struct FlagRegister {
+    replica: ReplicaId,
+
+    policy: FlagPolicy,
+
+    state: FlagState,
+}
+
+impl FlagRegister {
+    /// Creates a new, disabled flag owned by `replica`
+    pub fn new(replica: ReplicaId, policy: FlagPolicy) -> Self {
+        Self {
+            replica,
+            policy,
+            state: FlagState {
+                writes: BTreeMap::new(),
+                clock: BTreeMap::new(),
+            },
+        }
+    }
+
+    /// Returns the policy used to resolve concurrent writes
+    pub fn policy(&self) -> FlagPolicy {
+        self.policy
+    }
+
+    /// Enable the flag
+    pub fn enable(&mut self) {
+        self.write(true)
+    }
+
+    /// Disable the flag
+    pub fn disable(&mut self) {
+        self.write(false)
+    }
+
+    /// Returns `true` if the flag is enabled
+    pub fn is_enabled(&self) -> bool {
+        let enabled = self.state.writes.values().any(|&v| v);
+        let disabled = self.state.writes.values().any(|&v| !v);
+
+        match (enabled, disabled) {
+            (true, true) => self.policy == FlagPolicy::EnableWins,
+            (enabled, _) => enabled,
+        }
+    }
+
+    fn write(&mut self, value: bool) {
+        let counter = self.state.clock.entry(self.replica).or_insert(0);
+        *counter += 1;
+
+        let dot = Dot {
+            replica: self.replica,
+            counter: *counter,
+        };
+
+        // A write overrides every write that has been observed so far
+        self.state.writes.clear();
+        self.state.writes.insert(dot, value);
+    }
+}
+
+/// Flags are equal if they hold the same writes, whatever replica owns them
+impl PartialEq for FlagRegister {
+    fn eq(&self, other: &Self) -> bool {
+        self.state == other.state
+    }
+}
+
+impl Eq for FlagRegister {}
+
+impl CRDT for FlagRegister {
+    type State = FlagState;
+
+    fn merge(&mut self, other: Self::State) {
+        // Keep our writes that the other side either holds as well or has never seen
+        self.state
+            .writes
+            .retain(|dot, _| other.writes.contains_key(dot) || !other.has_seen(dot));
+
+        // Add the writes of the other side that we have never seen
+        for (dot, value) in other.writes {
+            if !self.state.has_seen(&dot) {
+                self.state.writes.insert(dot, value);
+            }
+        }
+
+        for (replica, counter) in other.clock {
+            let c = self.state.clock.entry(replica).or_insert(0);
+            *c = (*c).max(counter);
+        }
+    }
+
+    fn take(self) -> Self::State {
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::CRDT;
+
+    use super::{FlagPolicy, FlagRegister};
+
+    /// Concurrently enable the flag on one replica and disable it on another, starting from
+    /// `initial`, and return both replicas after they exchanged their states
+    fn concurrent_enable_disable(
+        policy: FlagPolicy,
+        initial: bool,
+    ) -> (FlagRegister, FlagRegister) {
+        let mut a = FlagRegister::new(ReplicaId::new(1), policy);
+        let mut b = FlagRegister::new(ReplicaId::new(2), policy);
+
+        if initial {
+            a.enable();
+        }
+        b.merge(a.clone().take());
+
+        a.enable();
+        b.disable();
+        assert_converges!(a, b);
+
+        let (sa, sb) = (a.clone().take(), b.clone().take());
+        a.merge(sb);
+        b.merge(sa);
+
+        (a, b)
+    }
+
+    #[test]
+    fn sequential_writes() {
+        let mut flag = FlagRegister::new(ReplicaId::new(1), FlagPolicy::EnableWins);
+        assert!(!flag.is_enabled());
+
+        flag.enable();
+        assert!(flag.is_enabled());
+
+        flag.disable();
+        assert!(!flag.is_enabled());
+    }
+
+    #[test]
+    fn enable_wins() {
+        for initial in [false, true] {
+            let (a, b) = concurrent_enable_disable(FlagPolicy::EnableWins, initial);
+            assert!(a.is_enabled());
+            assert!(b.is_enabled());
+        }
+    }
+
+    #[test]
+    fn disable_wins() {
+        for initial in [false, true] {
+            let (a, b) = concurrent_enable_disable(FlagPolicy::DisableWins, initial);
+            assert!(!a.is_enabled());
+            assert!(!b.is_enabled());
+        }
+    }
+
+    #[test]
+    fn write_after_merge_overrides_concurrent_writes() {
+        let (mut a, mut b) = concurrent_enable_disable(FlagPolicy::EnableWins, false);
+
+        a.disable();
+        b.merge(a.clone().take());
+
+        assert!(!a.is_enabled());
+        assert!(!b.is_enabled());
+    }
+}
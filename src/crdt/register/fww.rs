@@ -0,0 +1,167 @@
+//! This module defines a First-Write-Wins register, the counterpart of the LWW register for values
+//! that must never change once they have been set, such as a creation timestamp.
+//! The write with the lowest timestamp survives, later writes are ignored
+
+use crate::crdt::replica::ReplicaId;
+use crate::crdt::CRDT;
+
+/// The write held by a [`FWWRegister`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FWWState<T> {
+    value: T,
+
+    /// The timestamp, or sequence number, of the write
+    ts: u64,
+
+    /// The replica that wrote the value, used to break ties between values written concurrently
+    /// with the same timestamp
+    replica: ReplicaId,
+}
+
+impl<T> FWWState<T> {
+    /// Returns a reference to the value of this write
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Returns the timestamp of this write
+    pub fn timestamp(&self) -> u64 {
+        self.ts
+    }
+
+    /// Returns the replica that wrote the value
+    pub fn replica(&self) -> ReplicaId {
+        self.replica
+    }
+
+    /// Returns `true` if this write happened before `other` and wins over it when they are merged.
+    /// The write with the lowest timestamp wins, ties are broken by the lowest replica id
+    fn precedes(&self, other: &Self) -> bool {
+        (self.ts, self.replica) < (other.ts, other.replica)
+    }
+}
+
+/// A register that keeps the first value that has been written to it
+#[derive(Debug, Clone)]
+pub struct FWWRegister<T> {
+    replica: ReplicaId,
+
+    state: Option<FWWState<T>>,
+}
+
+impl<T> FWWRegister<T> {
+    /// Creates a new, unset register owned by `replica`
+    pub fn new(replica: ReplicaId) -> Self {
+        Self {
+            replica,
+            state: None,
+        }
+    }
+
+    /// Returns a reference to the value of this register, if it has been set
+    pub fn value(&self) -> Option<&T> {
+        self.state.as_ref().map(FWWState::value)
+    }
+
+    /// Returns the timestamp of the value of this register, if it has been set
+    pub fn timestamp(&self) -> Option<u64> {
+        self.state.as_ref().map(FWWState::timestamp)
+    }
+
+    /// Write `value` at time `ts` and return `true` if the register was unset and the value has
+    /// been kept.
+    /// A value that has been kept can still be replaced by a merge if another replica set the
+    /// register concurrently with an earlier timestamp
+    pub fn try_set(&mut self, value: T, ts: u64) -> bool {
+        if self.state.is_some() {
+            return false;
+        }
+
+        self.state = Some(FWWState {
+            value,
+            ts,
+            replica: self.replica,
+        });
+        true
+    }
+}
+
+/// Registers are equal if they hold the same write, whatever replica owns them
+impl<T: PartialEq> PartialEq for FWWRegister<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.state == other.state
+    }
+}
+
+impl<T: Eq> Eq for FWWRegister<T> {}
+
+impl<T> CRDT for FWWRegister<T> {
+    type State = Option<FWWState<T>>;
+
+    /// Keep the write with the lowest timestamp.
+    /// Concurrent values written with the same timestamp are resolved by keeping the value written
+    /// by the replica with the lowest id
+    fn merge(&mut self, other: Self::State) {
+        let Some(other) = other else {
+            return;
+        };
+
+        match &self.state {
+            Some(ours) if !other.precedes(ours) => {}
+            _ => self.state = Some(other),
+        }
+    }
+
+    fn take(self) -> Self::State {
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::{CRDTExt, CRDT};
+
+    use super::FWWRegister;
+
+    #[test]
+    fn first_write_is_kept() {
+        let mut reg = FWWRegister::new(ReplicaId::new(1));
+        assert_eq!(reg.value(), None);
+
+        assert!(reg.try_set(0xC0FFEE, 10));
+        assert!(!reg.try_set(0xBAD, 5));
+        assert!(!reg.try_set(0xDEAD, 20));
+
+        assert_eq!(reg.value(), Some(&0xC0FFEE));
+        assert_eq!(reg.timestamp(), Some(10));
+
+        // A later write from another replica is ignored as well
+        let mut late = FWWRegister::new(ReplicaId::new(2));
+        late.try_set(0xF00D, 11);
+        late.merge_into(&mut reg);
+        assert_eq!(reg.value(), Some(&0xC0FFEE));
+    }
+
+    #[test]
+    fn merge_keeps_earliest() {
+        let mut a = FWWRegister::new(ReplicaId::new(1));
+        let mut b = FWWRegister::new(ReplicaId::new(2));
+        let mut c = FWWRegister::new(ReplicaId::new(3));
+
+        a.try_set(0xBAD, 10);
+        b.try_set(0xC0FFEE, 5);
+        c.try_set(0xDEAD, 5);
+
+        assert_converges!(a.clone(), b.clone());
+        assert_converges!(b.clone(), c.clone());
+
+        b.merge_into(&mut a);
+        c.merge_into(&mut a);
+        assert_eq!(a.value(), Some(&0xC0FFEE));
+        assert_eq!(a.timestamp(), Some(5));
+
+        a.merge(FWWRegister::new(ReplicaId::new(4)).take());
+        assert_eq!(a.value(), Some(&0xC0FFEE));
+    }
+}
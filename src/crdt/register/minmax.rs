@@ -0,0 +1,217 @@
+//! This module defines registers that converge to the maximum or minimum value that has ever been
+//! written by any replica.
+//! Unlike LWW registers, they do not need any sequence number or clock to converge
+
+use std::cmp::Ordering;
+
+use crate::crdt::CRDT;
+
+/// A floating-point number that is totally ordered, so that it can be held by a [`MaxRegister`] or
+/// a [`MinRegister`].
+///
+/// Numbers are ordered with `total_cmp`, which gives NaN a fixed position in the order: a positive
+/// NaN is greater than positive infinity, and a negative NaN is lower than negative infinity.
+/// Two numbers are only equal if they have the same bits, `-0.0` is lower than `+0.0`.
+/// This keeps merges deterministic, and replicas converge whatever the order of the writes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Total<T>(pub T);
+
+macro_rules! impl_total {
+    ($($t:ty),*) => {$(
+        impl PartialEq for Total<$t> {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+
+        impl Eq for Total<$t> {}
+
+        impl PartialOrd for Total<$t> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for Total<$t> {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+    )*};
+}
+
+impl_total!(f32, f64);
+
+/// A register that always holds the maximum value that has been written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaxRegister<T> {
+    value: T,
+}
+
+impl<T> MaxRegister<T>
+where
+    T: Ord,
+{
+    /// Creates a new register that holds `value`
+    pub fn new(value: T) -> Self {
+        Self { value }
+    }
+
+    /// Returns a reference to the value that this register holds
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Write a new value and return `true` if it is greater than the current one and has been kept
+    pub fn set(&mut self, value: T) -> bool {
+        if value <= self.value {
+            return false;
+        }
+
+        self.value = value;
+        true
+    }
+}
+
+impl<T> CRDT for MaxRegister<T>
+where
+    T: Ord,
+{
+    type State = T;
+
+    fn merge(&mut self, other: Self::State) {
+        self.set(other);
+    }
+
+    fn take(self) -> Self::State {
+        self.value
+    }
+}
+
+/// A register that always holds the minimum value that has been written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MinRegister<T> {
+    value: T,
+}
+
+impl<T> MinRegister<T>
+where
+    T: Ord,
+{
+    /// Creates a new register that holds `value`
+    pub fn new(value: T) -> Self {
+        Self { value }
+    }
+
+    /// Returns a reference to the value that this register holds
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Write a new value and return `true` if it is lower than the current one and has been kept
+    pub fn set(&mut self, value: T) -> bool {
+        if value >= self.value {
+            return false;
+        }
+
+        self.value = value;
+        true
+    }
+}
+
+impl<T> CRDT for MinRegister<T>
+where
+    T: Ord,
+{
+    type State = T;
+
+    fn merge(&mut self, other: Self::State) {
+        self.set(other);
+    }
+
+    fn take(self) -> Self::State {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::CRDT;
+
+    use super::{MaxRegister, MinRegister, Total};
+
+    #[test]
+    fn max_never_decreases() {
+        let mut reg = MaxRegister::new(0xBAD);
+
+        assert!(reg.set(0xC0FFEE));
+        assert!(!reg.set(0xBAD));
+
+        reg.merge(0xDEAD);
+        assert_eq!(*reg.value(), 0xC0FFEE);
+    }
+
+    #[test]
+    fn min_never_increases() {
+        let mut reg = MinRegister::new(0xC0FFEE);
+
+        assert!(reg.set(0xBAD));
+        assert!(!reg.set(0xC0FFEE));
+
+        reg.merge(0xDEAD);
+        assert_eq!(*reg.value(), 0xBAD);
+    }
+
+    #[test]
+    fn merge_order_does_not_matter() {
+        let writes = [0xF00D, 0xBAD, 0xC0FFEE, 0xDEAD];
+
+        let mut forward = (MaxRegister::new(0), MinRegister::new(u64::MAX));
+        for w in writes {
+            forward.0.merge(w);
+            forward.1.merge(w);
+        }
+
+        let mut backward = (MaxRegister::new(0), MinRegister::new(u64::MAX));
+        for w in writes.into_iter().rev() {
+            backward.0.merge(w);
+            backward.1.merge(w);
+        }
+
+        assert_eq!(forward, backward);
+        assert_eq!(*forward.0.value(), 0xC0FFEE);
+        assert_eq!(*forward.1.value(), 0xBAD);
+    }
+
+    #[test]
+    fn floats_with_nan_converge() {
+        let writes = [1.5, f64::NAN, f64::NEG_INFINITY, -f64::NAN, 3.0, -0.0, 0.0];
+
+        let mut forward = (MaxRegister::new(Total(0.0)), MinRegister::new(Total(0.0)));
+        for w in writes {
+            forward.0.merge(Total(w));
+            forward.1.merge(Total(w));
+        }
+
+        let mut backward = (MaxRegister::new(Total(0.0)), MinRegister::new(Total(0.0)));
+        for w in writes.into_iter().rev() {
+            backward.0.merge(Total(w));
+            backward.1.merge(Total(w));
+        }
+
+        assert_eq!(forward, backward);
+        assert!(forward.0.value().0.is_nan() && forward.0.value().0.is_sign_positive());
+        assert!(forward.1.value().0.is_nan() && forward.1.value().0.is_sign_negative());
+
+        // Without NaN, the extremes are the regular ones
+        let mut max = MaxRegister::new(Total(f64::NEG_INFINITY));
+        let mut min = MinRegister::new(Total(f64::INFINITY));
+        for w in [2.0_f64, -0.0, 0.0, -7.25] {
+            max.merge(Total(w));
+            min.merge(Total(w));
+        }
+        assert_eq!(max.value().0, 2.0);
+        assert_eq!(min.value().0, -7.25);
+        assert!(!max.set(Total(2.0)));
+    }
+}
@@ -0,0 +1,3 @@
+pub mod flag;
+pub mod fww;
+pub mod minmax;
@@ -0,0 +1,161 @@
+//! Identifiers of the replicas taking part in a replication
+
+use std::collections::HashSet;
+
+/// Uniquely identifies a replica.
+///
+/// Replica ids are ordered, which is how concurrent writes made with the same sequence number are
+/// resolved: the write of the highest replica id wins. A replica can be given a priority, see
+/// [`Self::with_priority`], so that its writes win such ties over the writes of the replicas with
+/// a lower priority, e.g to let a primary replica win over its secondaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ReplicaId(u64);
+
+impl ReplicaId {
+    /// The number of low bits of the raw representation that hold the id, the high bits hold the
+    /// priority
+    const ID_BITS: u32 = 56;
+
+    /// Creates a new replica identifier, with the default priority
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` does not fit in 56 bits, the high bits of the raw representation hold the
+    /// priority, see [`Self::with_priority`]
+    pub const fn new(id: u64) -> Self {
+        Self::with_priority(id, 0)
+    }
+
+    /// Creates a replica identifier from its raw representation, including its priority, as
+    /// returned by [`Self::get`]
+    pub const fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+
+    /// Creates a new replica identifier with a priority.
+    /// Identifiers are ordered by priority first, then by id.
+    /// The priority is stored in the 8 high bits of the raw representation, see [`Self::get`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` does not fit in 56 bits
+    pub const fn with_priority(id: u64, priority: u8) -> Self {
+        assert!(id >> Self::ID_BITS == 0, "a replica id must fit in 56 bits");
+
+        Self((priority as u64) << Self::ID_BITS | id)
+    }
+
+    /// Returns the priority of this identifier, zero unless it has been created with
+    /// [`Self::with_priority`]
+    pub const fn priority(self) -> u8 {
+        (self.0 >> Self::ID_BITS) as u8
+    }
+
+    /// Returns the raw representation of this identifier
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Derive an identifier from `seed`, the same seed always gives the same identifier.
+    /// The seed is scrambled so that close seeds give unrelated identifiers, which always have the
+    /// default priority. Meant for reproducible tests, see [`SeededIdGen`] to get several distinct
+    /// identifiers
+    pub const fn from_seed(seed: u64) -> Self {
+        // splitmix64 finalizer
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        Self(z >> (u64::BITS - Self::ID_BITS))
+    }
+}
+
+/// A generator of distinct replica identifiers, derived from a seed.
+/// Two generators created from the same seed yield the same sequence of identifiers, so that a
+/// failing test can be replayed exactly
+#[derive(Debug, Clone)]
+pub struct SeededIdGen {
+    next: u64,
+    seen: HashSet<ReplicaId>,
+}
+
+impl SeededIdGen {
+    /// Create a generator from `seed`
+    pub fn new(seed: u64) -> Self {
+        Self {
+            next: seed,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+impl Iterator for SeededIdGen {
+    type Item = ReplicaId;
+
+    /// Returns an identifier that has not been returned yet by this generator
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let id = ReplicaId::from_seed(self.next);
+            self.next = self.next.wrapping_add(1);
+            if self.seen.insert(id) {
+                return Some(id);
+            }
+        }
+    }
+}
+
+/// Panics if `id` does not fit in 56 bits, see [`ReplicaId::new`]
+impl From<u64> for ReplicaId {
+    fn from(id: u64) -> Self {
+        Self::new(id)
+    }
+}
+
+/// Uniquely identifies a write made by a replica
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct Dot {
+    pub(crate) replica: ReplicaId,
+    pub(crate) counter: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{ReplicaId, SeededIdGen};
+
+    #[test]
+    fn same_seed_same_ids() {
+        assert_eq!(
+            ReplicaId::from_seed(0xC0FFEE),
+            ReplicaId::from_seed(0xC0FFEE)
+        );
+        assert_ne!(ReplicaId::from_seed(0xC0FFEE), ReplicaId::from_seed(0xBAD));
+        assert_eq!(ReplicaId::from_seed(0xF00D).priority(), 0);
+
+        let a: Vec<_> = SeededIdGen::new(0xC0FFEE).take(1000).collect();
+        let b: Vec<_> = SeededIdGen::new(0xC0FFEE).take(1000).collect();
+        assert_eq!(a, b);
+        assert_eq!(a.iter().collect::<HashSet<_>>().len(), a.len());
+
+        let c: Vec<_> = SeededIdGen::new(0xBAD).take(1000).collect();
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn ids_can_not_set_a_priority() {
+        let id = ReplicaId::new((1 << 56) - 1);
+        assert_eq!(id.priority(), 0);
+        assert!(id < ReplicaId::with_priority(0, 1));
+
+        let primary = ReplicaId::with_priority(0xC0FFEE, 3);
+        assert_eq!(ReplicaId::from_raw(primary.get()), primary);
+    }
+
+    #[test]
+    #[should_panic(expected = "a replica id must fit in 56 bits")]
+    fn new_rejects_ids_over_56_bits() {
+        ReplicaId::new(1 << 56);
+    }
+}
@@ -0,0 +1,110 @@
+//! This module defines a tracker of the versions that every replica has acknowledged, which is
+//! used to know when it is safe to garbage collect tombstones
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use super::lww::map::MapState;
+use super::replica::ReplicaId;
+
+/// Error returned when acknowledging a version for a replica that is not tracked, see
+/// [`StabilityTracker::track`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownReplica(pub ReplicaId);
+
+impl fmt::Display for UnknownReplica {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "replica {:?} is not tracked", self.0)
+    }
+}
+
+impl std::error::Error for UnknownReplica {}
+
+/// Tracks, for every key, the highest sequence number that every replica of a cluster has
+/// acknowledged.
+///
+/// Sequence numbers are counted per key, so a replica that acknowledged a high sequence number for
+/// a key may not have observed a lower one of another key: acknowledgements are never shared
+/// between keys
+#[derive(Debug, Clone)]
+pub struct StabilityTracker<K> {
+    acks: HashMap<ReplicaId, HashMap<K, u64>>,
+}
+
+impl<K> Default for StabilityTracker<K> {
+    fn default() -> Self {
+        Self {
+            acks: HashMap::new(),
+        }
+    }
+}
+
+impl<K> StabilityTracker<K> {
+    /// Create a new tracker for the given replicas, none of which has acknowledged anything yet
+    pub fn new(replicas: impl IntoIterator<Item = ReplicaId>) -> Self {
+        Self {
+            acks: replicas.into_iter().map(|r| (r, HashMap::new())).collect(),
+        }
+    }
+
+    /// Start tracking a new replica, which has not acknowledged anything yet
+    pub fn track(&mut self, replica: ReplicaId) {
+        self.acks.entry(replica).or_default();
+    }
+
+    /// Stop tracking a replica, e.g because it left the cluster
+    pub fn untrack(&mut self, replica: ReplicaId) {
+        self.acks.remove(&replica);
+    }
+}
+
+impl<K> StabilityTracker<K>
+where
+    K: Eq + Hash,
+{
+    /// Record that `replica` has observed the version `seq` of the key `k`, or a more recent one.
+    /// Acknowledgements never go backward, acknowledging a lower sequence number has no effect.
+    /// Returns an error if `replica` is not tracked
+    pub fn ack(&mut self, replica: ReplicaId, k: K, seq: u64) -> Result<(), UnknownReplica> {
+        let acks = self.acks.get_mut(&replica).ok_or(UnknownReplica(replica))?;
+        let ack = acks.entry(k).or_insert(0);
+        *ack = (*ack).max(seq);
+        Ok(())
+    }
+
+    /// Record that `replica` holds `state`, i.e that it has observed the version of every key of
+    /// the state, including its tombstones.
+    /// Returns an error if `replica` is not tracked
+    pub fn ack_state<V>(
+        &mut self,
+        replica: ReplicaId,
+        state: &MapState<K, V>,
+    ) -> Result<(), UnknownReplica>
+    where
+        K: Clone,
+    {
+        let acks = self.acks.get_mut(&replica).ok_or(UnknownReplica(replica))?;
+        for (k, seq) in state.seqs() {
+            let ack = acks.entry(k.clone()).or_insert(0);
+            *ack = (*ack).max(seq);
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if every tracked replica has observed the version `seq` of the key `k`, or a
+    /// more recent one. A tombstone of `k` written with `seq` can then be purged safely.
+    /// Nothing is stable while no replica is tracked
+    pub fn is_stable<Q>(&self, k: &Q, seq: u64) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        !self.acks.is_empty()
+            && self
+                .acks
+                .values()
+                .all(|acks| acks.get(k).is_some_and(|&ack| ack >= seq))
+    }
+}
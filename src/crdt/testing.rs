@@ -0,0 +1,227 @@
+//! Helpers to test the convergence of CRDTs
+
+/// Merge two replicas in both directions and assert that every replica converged to the same
+/// state, whatever the direction of the merge.
+///
+/// Both replicas must implement [`CRDT`](crate::crdt::CRDT), [`Clone`], [`PartialEq`] and
+/// [`Debug`](std::fmt::Debug). The replicas are cloned and left untouched. On failure, every
+/// resulting replica is printed
+macro_rules! assert_converges {
+    ($a:expr, $b:expr $(,)?) => {{
+        use $crate::crdt::CRDT as _;
+
+        let (a, b) = (&$a, &$b);
+
+        let mut ab = a.clone();
+        ab.merge(b.clone().take());
+
+        let mut ba = b.clone();
+        ba.merge(a.clone().take());
+
+        // Merging again must not change anything either
+        let mut aba = ab.clone();
+        aba.merge(a.clone().take());
+
+        let mut bab = ba.clone();
+        bab.merge(b.clone().take());
+
+        if !(ab == ba && ab == aba && ab == bab) {
+            panic!(
+                "replicas do not converge\n a <- b: {:#?}\n b <- a: {:#?}\n(a <- b) <- a: {:#?}\n(b <- a) <- b: {:#?}",
+                ab, ba, aba, bab
+            );
+        }
+    }};
+}
+
+use std::fmt::Debug;
+
+use crate::crdt::lww::map::LWWMap;
+use crate::crdt::CRDT;
+
+/// Take the state of `crdt`, rebuild a replica from it with `rebuild` and assert that nothing has
+/// been lost on the way.
+///
+/// The rebuilt replica must be equal to `crdt` and give back the same state. Merging the state
+/// again into the rebuilt replica, or the rebuilt state into `crdt`, must not change anything.
+/// A state that drops information needed to converge, such as the seq of a removed key, fails
+/// this check
+pub(crate) fn assert_state_roundtrips<C>(crdt: &C, rebuild: impl Fn(C::State) -> C)
+where
+    C: CRDT + Clone + PartialEq + Debug,
+    C::State: Clone + PartialEq + Debug,
+{
+    let state = crdt.clone().take();
+
+    let rebuilt = rebuild(state.clone());
+    assert_eq!(
+        &rebuilt, crdt,
+        "the rebuilt replica differs from the original one"
+    );
+    assert_eq!(
+        rebuilt.clone().take(),
+        state,
+        "the state changed after a round trip"
+    );
+
+    let mut merged = rebuilt.clone();
+    merged.merge(state);
+    assert_eq!(merged, rebuilt, "merging the state back is not idempotent");
+
+    let mut merged = crdt.clone();
+    merged.merge(rebuilt.take());
+    assert_eq!(&merged, crdt, "merging the rebuilt state is not idempotent");
+}
+
+/// Take the state of `crdt`, merge it into an empty replica and assert that the merged replica is
+/// equal to `crdt` and gives back the same state.
+/// Unlike [`assert_state_roundtrips`], the state goes through the merge of keys that the replica
+/// does not hold yet, which must keep their versions as they are
+pub(crate) fn assert_state_merges_into_empty<C>(crdt: &C)
+where
+    C: CRDT + Clone + Default + PartialEq + Debug,
+    C::State: Clone + PartialEq + Debug,
+{
+    let state = crdt.clone().take();
+
+    let mut merged = C::default();
+    merged.merge(state.clone());
+    assert_eq!(
+        &merged, crdt,
+        "the state merged into an empty replica differs from the original one"
+    );
+    assert_eq!(
+        merged.take(),
+        state,
+        "the state changed after being merged into an empty replica"
+    );
+}
+
+/// A small xorshift generator, to get a reproducible series of operations
+#[derive(Debug, Clone)]
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    /// Create a generator from a non-zero `seed`
+    pub(crate) fn new(seed: u64) -> Self {
+        assert_ne!(seed, 0, "a xorshift generator can not be seeded with zero");
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Apply `ops` random inserts and removals to `map`, over the keys `0..16`
+pub(crate) fn scramble(map: &mut LWWMap<u64, u64>, rng: &mut XorShift, ops: usize) {
+    for _ in 0..ops {
+        let k = rng.next_u64() % 16;
+        match rng.next_u64() % 3 {
+            0 => {
+                map.remove(&k);
+            }
+            _ => {
+                map.insert(k, rng.next_u64());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crdt::lww::map::LWWMap;
+    use crate::crdt::lww::register::LWWRegister;
+    use crate::crdt::replica::ReplicaId;
+    use crate::crdt::CRDT;
+
+    use super::{assert_state_merges_into_empty, assert_state_roundtrips, scramble, XorShift};
+
+    #[test]
+    fn registers_converge() {
+        let mut a = LWWRegister::new(0xC0FFEE);
+        a.update(0xBAD);
+
+        let b = LWWRegister::with_replica(0xF00D, ReplicaId::new(1));
+        assert_converges!(a, b);
+
+        let b = LWWRegister::with_replica(0xF00D, ReplicaId::new(1));
+        assert_converges!(LWWRegister::new(0xC0FFEE), b);
+    }
+
+    #[test]
+    fn maps_converge() {
+        let mut a = LWWMap::new();
+        a.insert("key", 0xC0FFEE);
+        a.insert("key", 0xBAD);
+
+        assert_converges!(a, LWWMap::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "replicas do not converge")]
+    fn detects_divergence() {
+        // Two different values written with the same seq by the same replica can not be told apart
+        assert_converges!(LWWRegister::new(0xC0FFEE), LWWRegister::new(0xBAD));
+    }
+
+    #[test]
+    fn forked_replicas_converge() {
+        let mut rng = XorShift::new(0xC0FFEE);
+        let mut seed = LWWMap::new();
+        scramble(&mut seed, &mut rng, 32);
+
+        let mut replicas: Vec<_> = (1..=8).map(|id| seed.fork(ReplicaId::new(id))).collect();
+        for replica in &mut replicas {
+            scramble(replica, &mut rng, 100);
+        }
+
+        // Full mesh: every replica merges the state of every other replica
+        let states: Vec<_> = replicas.iter().map(|r| r.clone().take()).collect();
+        for replica in &mut replicas {
+            for state in &states {
+                replica.merge(state.clone());
+            }
+        }
+
+        for replica in &replicas[1..] {
+            assert_eq!(replica, &replicas[0]);
+            assert_eq!(replica.digest(), replicas[0].digest());
+        }
+    }
+
+    #[test]
+    fn register_states_round_trip() {
+        let mut reg = LWWRegister::with_replica(0xC0FFEE, ReplicaId::new(1));
+        assert_state_roundtrips(&reg, LWWRegister::from_state);
+
+        reg.update(0xBAD);
+        reg.update(0xF00D);
+        assert_state_roundtrips(&reg, LWWRegister::from_state);
+    }
+
+    #[test]
+    fn map_states_round_trip() {
+        let mut map = LWWMap::with_replica(ReplicaId::new(1));
+        assert_state_roundtrips(&map, LWWMap::from);
+        assert_state_merges_into_empty(&map);
+
+        map.insert(0xC0FFEE, 0xBAD);
+        map.insert(0xF00D, 0xDEAD);
+        map.remove(&0xC0FFEE);
+        assert_state_roundtrips(&map, LWWMap::from);
+        assert_state_merges_into_empty(&map);
+
+        let mut rng = XorShift::new(0xBAD);
+        scramble(&mut map, &mut rng, 200);
+        assert_state_roundtrips(&map, LWWMap::from);
+        assert_state_merges_into_empty(&map);
+
+        map.reset([(0xC0FFEE, 0xF00D)]);
+        assert_state_roundtrips(&map, LWWMap::from);
+        assert_state_merges_into_empty(&map);
+    }
+}
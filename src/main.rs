@@ -1,5 +1,3 @@
-mod crdt;
-
 fn main() {
     println!("Hello, world!");
 }